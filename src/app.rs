@@ -1,197 +1,377 @@
-use crate::config::{CliConfig, SaveType};
-use easy_rss::RssParser;
-use std::io::Write;
-use redis::Commands;
-use chrono::prelude::*;
-use std::ops::AddAssign;
-use mysql::prelude::*;
+use crate::canonical::Canonicalizer;
+use crate::classify::Classifier;
+use crate::cluster::{self, ClusterIndex};
+use crate::config::CliConfig;
+use crate::fetch::Pages;
+use crate::filter::Filter;
+use crate::item::Item;
+use crate::metrics;
+use crate::readability;
+use crate::sanitize::{self, Sanitizer};
+use crate::sink::{self, Sink, SinkReport};
+use crate::source::{self, Source};
+use crate::tag::Tagger;
+
+
+#[derive(Debug,Clone,Default)]
+pub struct RunReport{
+    pub items: usize,
+    pub new_items: usize,
+    pub filtered: usize,
+    pub suppressed: usize,
+    pub sinks: Vec<SinkReport>,
+}
 
 
 pub struct App<'a,>{
     conf:&'a CliConfig,
-    parser: RssParser,
-    now:DateTime<Local>,
+    source: Box<dyn Source>,
+    sinks: Vec<Box<dyn Sink>>,
+    filters: Vec<Filter>,
+    tagger: Tagger,
+    classifier: Option<Classifier>,
+    clusters: ClusterIndex,
+    canonical: Option<Canonicalizer>,
+    sanitizer: Sanitizer,
+    pages: Pages,
 }
 
 impl<'a> App<'a>{
     pub fn from(conf:&'a CliConfig)->Self{
+        let sinks = sink::from_config(conf,&chrono::Local::now());
+        Self::new(conf,source::from_config(conf),sinks)
+    }
+
+
+    // Embedders bring their own source and sinks, `conf` still drives fetch_content.
+    pub fn new(conf:&'a CliConfig,source:Box<dyn Source>,sinks:Vec<Box<dyn Sink>>)->Self{
+        let filter = Filter::from(&conf.filters).unwrap_or_else(|(path,e)|{
+            tracing::warn!("Failed by Config[filters.{}]: {}",path,e);
+            Filter::default()
+        });
+        let tagger = Tagger::from(&conf.tags).unwrap_or_else(|(path,e)|{
+            tracing::warn!("Failed by Config[tags.{}]: {}",path,e);
+            Tagger::default()
+        });
+        let classifier = if !conf.classifier.model.is_empty() {
+            Classifier::load(conf.classifier.model.as_str())
+                .map_err(|e| tracing::warn!(model = %conf.classifier.model,"Failed by load classifier: {}",e))
+                .ok()
+        }else{
+            None
+        };
+
         Self{
             conf,
-            parser:RssParser::new(),
-            now:chrono::Local::now()
+            source,
+            sinks,
+            filters: vec![filter],
+            tagger,
+            classifier,
+            clusters: ClusterIndex::default(),
+            canonical: conf.canonical.as_ref().map(|c| Canonicalizer::new(c,conf.url.as_str())),
+            sanitizer: Sanitizer::new(&conf.sanitize,conf.url.as_str()),
+            // Articles are other pages than the feed, their charset is detected rather than the feed's.
+            pages: Pages::new("auto",conf.chrome_path.as_str(),&conf.http),
         }
     }
 
-    pub fn run(&mut self)->Result<(), std::io::Error>{
-        self.parser.publish_tag = self.conf.publish_tag.clone();
-        self.parser.title_tag = self.conf.title_tag.clone();
-        self.parser.link_tag = self.conf.link_tag.clone();
-        self.parser.author_tag = self.conf.author_tag.clone();
-        self.parser.description_tag = self.conf.description_tag.clone();
-        self.parser.guid_tag = self.conf.guid_tag.clone();
-        self.parser.publish_tag = self.conf.publish_tag.clone();
-
-
-        match self.parser.request_xml(
-            self.conf.url.as_str(),
-            self.conf.charset.as_str()
-        ){
-            Ok(ret) => {
-                self.parser.set_xml(ret);
-            },
-            Err(_e) =>{
-                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Failed by request xml."))
+
+    pub fn add_sink(&mut self,sink:Box<dyn Sink>)->&mut Self{
+        self.sinks.push(sink);
+        self
+    }
+
+
+    // Applied after the feed rules, e.g. the global `--filters` file.
+    pub fn add_filter(&mut self,filter:Filter)->&mut Self{
+        self.filters.push(filter);
+        self
+    }
+
+
+    // Shared by the feeds to cluster their items, kept in memory only by default.
+    pub fn set_cluster_index(&mut self,index:ClusterIndex)->&mut Self{
+        self.clusters = index;
+        self
+    }
+
+
+    // A guid that is the link ( or any url ) gets the same spelling, so the uid follows the link.
+    fn canonicalize(&mut self,item:&mut Item){
+        let canonical = match &self.canonical {
+            Some(c) => c,
+            None => return
+        };
+
+        let mut link = canonical.canonical(item.link.as_str());
+        if canonical.follow(link.as_str()) {
+            match self.pages.resolve(link.as_str()) {
+                Ok(target) => link = canonical.canonical(target.as_str()),
+                Err(e) => tracing::warn!(link = %link,"Failed by follow redirect: {}",e)
             }
+        }
+
+        if item.guid == item.link {
+            item.guid = link.clone();
+        }else if item.guid.starts_with("http://") || item.guid.starts_with("https://") {
+            item.guid = canonical.canonical(item.guid.as_str());
+        }
+        item.link = link;
+    }
+
+
+    // Kept, tagged, classified and clustered items, and the number of filtered and suppressed ones.
+    fn prepare(&mut self,mut items:Vec<Item>)->(Vec<Item>,usize,usize){
+        for item in items.iter_mut() {
+            self.canonicalize(item);
+            item.uid = item.make_uid(&self.conf.uid);
+            item.description_html = self.sanitizer.html(item.description.as_str(),item.link.as_str());
+            item.description_text = sanitize::text(item.description_html.as_str());
+        }
+        let (mut items,dropped) = self.filters.iter().fold((items,0),|(items,dropped),f|{
+            let (kept,n) = f.apply(items);
+            (kept,dropped + n)
+        });
+        self.tagger.apply(items.as_mut_slice());
+        if let Some(classifier) = &self.classifier {
+            classifier.apply(items.as_mut_slice(),self.conf.classifier.min_confidence);
+        }
+
+        let conf = match &self.conf.cluster {
+            Some(c) => c,
+            None => return (items,dropped,0)
         };
+        let now = chrono::Local::now().timestamp();
+        let mut suppressed = 0;
+        items.retain_mut(|item|{
+            let fingerprint = match cluster::simhash(item) {
+                Some(f) => f,
+                None => return true
+            };
+            let (cluster_id,duplicate) = self.clusters.assign(item.uid().as_str(),fingerprint,conf.threshold,now);
+            item.fingerprint = format!("{:016x}",fingerprint);
+            item.cluster_id = cluster_id;
+            if duplicate && conf.suppress {
+                tracing::debug!(uid = %item.uid(),cluster = %item.cluster_id,"suppressed");
+                suppressed += 1;
+                return false;
+            }
+            true
+        });
+        (items,dropped,suppressed)
+    }
 
 
-        if !self.parser.check_xml() {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Failed by check xml."));
+    // Fetch the article behind the item link and keep its readable part.
+    fn enrich(&mut self,item:&mut Item){
+        if self.conf.fetch_content.is_empty() || item.link.is_empty() {
+            return;
         }
 
-        match self.conf.save_type {
-            SaveType::Redis =>{
-                let data = self.parser.parse_json()?;
-                let address = self.conf.driver_url.clone();
-                let mut table_name = self.conf.table_name.clone();
-                if self.conf.append_date.len() > 0 {
-                    table_name.add_assign("_");
-                    let now_date = self.now.format(self.conf.append_date.as_str()).to_string();
-                    table_name.add_assign(now_date.as_str());
+        let fetcher = self.conf.fetch_content.clone();
+        match self.pages.get_html(item.link.as_str(),fetcher.as_str()) {
+            Ok(html) => {
+                if let Some(article) = readability::extract(html.as_str()) {
+                    // Kept markup of the page, handlers and styles included, goes through the description allowlist.
+                    item.content = self.sanitizer.html(article.html.as_str(),item.link.as_str());
                 }
+            }
+            Err(e) => {
+                tracing::warn!(link = %item.link,"Failed by fetch content: {}",e);
+            }
+        }
+    }
 
-                let redis_client = match redis::Client::open(address) {
-                    Ok(con) => con,
-                    Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
-                } ;
 
+    // Saved by every sink it is routed to already, so there is nothing to enrich.
+    // A sink that cannot tell takes the item as new and is not asked again, its error goes to its report.
+    fn stored(&mut self,item:&Item,errors:&mut [Option<String>])->bool{
+        if self.sinks.is_empty() {
+            return false;
+        }
+        let uid = item.uid();
+        for (sink,error) in self.sinks.iter_mut().zip(errors.iter_mut()) {
+            if !sink.accepts(item) {
+                continue;
+            }
+            if error.is_some() {
+                return false;
+            }
+            match sink.contains(uid.as_str()) {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(e) => {
+                    tracing::warn!(sink = %sink.name(),uid = %uid,"Failed by check stored: {}",e);
+                    *error = Some(format!("stored check: {}",e));
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+
+    pub fn fetch(&mut self)->Result<Vec<Item>, std::io::Error>{
+        let items = self.source.fetch()?;
+        Ok(self.prepare(items).0)
+    }
+
+
+    pub fn charset(&self)->String{
+        self.source.charset()
+    }
+
+
+    pub fn status(&self)->u16{
+        self.source.status()
+    }
 
-                let mut connect = match redis_client.get_connection(){
-                    Ok(con) => con,
-                    Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
-                } ;
 
+    pub fn run(&mut self)->Result<RunReport, std::io::Error>{
+        let started = std::time::Instant::now();
+        let items = self.source.fetch()?;
+        metrics::fetch_duration(self.conf.url.as_str(),started.elapsed().as_secs_f64());
 
-                if let Err(e) = connect.set::<String,&[u8],String>(table_name,data.as_bytes()) {
-                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
-                } ;
+        let total = items.len();
+        let (items,filtered,suppressed) = self.prepare(items);
+        if let Some(conf) = &self.conf.cluster {
+            if let Err(e) = self.clusters.save(conf.window,chrono::Local::now().timestamp()) {
+                tracing::error!("Failed by save clusters: {}",e);
             }
+        }
+
+        let (new_items,sinks) = self.save_items(items);
+        metrics::items(self.conf.url.as_str(),total,new_items,filtered,suppressed);
 
-            SaveType::File =>{
-                let data = self.parser.parse_json()?;
-                let mut filename = self.conf.table_name.clone();
+        Ok(RunReport{
+            items: total,
+            new_items,
+            filtered,
+            suppressed,
+            sinks,
+        })
+    }
 
-                if self.conf.append_date.len() > 0 {
-                    filename = filename.replace(".json","");
-                    let now_date = self.now.format(self.conf.append_date.as_str()).to_string();
-                    filename.add_assign("_");
-                    filename.add_assign(now_date.as_str());
+
+    // Every item stored by the sinks of this feed, once per uid.
+    pub fn export(&mut self)->Result<Vec<Item>, std::io::Error>{
+        let mut uids = std::collections::HashSet::new();
+        let mut items = Vec::new();
+        for sink in self.sinks.iter_mut() {
+            for val in sink.export()? {
+                if uids.insert(val.uid()) {
+                    items.push(val);
                 }
+            }
+        }
+        Ok(items)
+    }
 
-                if !filename.contains(".json") {
-                    filename.add_assign(".json");
-                };
 
-                let fd = std::fs::OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .open(filename.as_str())?;
-                let mut writer = std::io::BufWriter::new(fd);
-                writer.write_all(data.as_bytes())?;
+    // Look an item up by uid in every table/key/file written for this feed.
+    pub fn show(&mut self,uid:&str)->Result<Option<Item>, std::io::Error>{
+        for sink in self.sinks.iter_mut() {
+            if let Some(found) = sink.find(uid)? {
+                return Ok(Some(found));
             }
+        }
+        Ok(None)
+    }
 
-            SaveType::MySQL =>{
-                let data = self.parser.parse_vec()?;
-                let address = self.conf.driver_url.clone();
-                let mut table_name = self.conf.table_name.clone();
-                if self.conf.append_date.len() > 0 {
-                    table_name.add_assign("_");
-                    let now_date = self.now.format(self.conf.append_date.as_str()).to_string();
-                    table_name.add_assign(now_date.as_str());
-                }
 
+    // Every sink gets the same batch, a failed sink does not stop the others and is reported with its error.
+    pub fn save(&mut self,items:Vec<Item>)->Result<Vec<SinkReport>, std::io::Error>{
+        Ok(self.save_items(items).1)
+    }
 
-                let mysql_client = match mysql::Pool::new(address) {
-                    Ok(con) => con,
-                    Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
-                } ;
-                let mut connect = match mysql_client.get_conn() {
-                    Ok(c) => c,
-                    Err(e) =>return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
-                };
-
-                let mut transaction = match connect.start_transaction(mysql::TxOpts::default()) {
-                    Ok(c) => c,
-                    Err(e) =>return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
-                };
-
-                let create_sql = format!(r#"CREATE TABLE IF NOT EXISTS `{}` (
-                        `uid` char(32) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
-                        `title` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
-                        `link` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
-                        `author` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
-                        `description` LONGTEXT NOT NULL COLLATE 'utf8mb4_unicode_ci',
-                        `guid` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
-                        `publish` varchar(50) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
-                        `create_time` int unsigned NOT NULL,
-                        PRIMARY KEY (`uid`)
-                    )COLLATE=utf8mb4_unicode_ci ENGINE=InnoDB CHARSET=utf8mb4"#,
-                    table_name
-                );
-
-
-                if let Err(e) = transaction.query_drop(create_sql) {
-                    transaction.rollback().unwrap();
-                    return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()));
-                }
 
+    fn save_items(&mut self,mut items:Vec<Item>)->(usize,Vec<SinkReport>){
+        let mut new_items = 0;
+        let mut errors = vec![None;self.sinks.len()];
+        for val in items.iter_mut() {
+            let uid = val.uid();
+            if !self.stored(val,errors.as_mut_slice()) {
+                let _span = tracing::debug_span!("item",uid = %uid).entered();
+                new_items += 1;
+                self.enrich(val);
+            }
+        }
 
-                let query_sql ="SELECT `uid` FROM `?` WHERE `uid` = '?'";
-                data.into_iter().for_each(|val|{
-
-                    let title = val.title.clone();
-                    let link = val.link.clone();
-                    let author = val.author.clone();
-                    let desc = val.description.clone();
-                    let guid = val.guid.clone();
-                    let publish = val.publish.clone();
-                    let create_time = self.now.timestamp().to_string();
-
-                    let uid = if val.guid.len() > 0 {
-                        val.guid.clone()
-                    }else{
-                        val.link.clone()
-                    };
-                    let uid = format!("{:x}",md5::compute(uid.as_bytes()));
-                    let show_uid = uid.clone();
-
-                    let exists = transaction.exec_first::<usize, _, _>(query_sql, (table_name.clone(), uid.clone(),));
-                    if exists.is_err(){
-                        let insert_sql = format!(
-                            r"INSERT INTO `{}` VALUES( ? , ? , ? , ? , ? , ? , ? , ? )",
-                            table_name
-                        );
-
-                        if let Ok(_)  = transaction.exec_drop(
-                            insert_sql, (
-                                uid,title,link,author,
-                                desc,guid,publish,create_time
-                            )
-                        ) {
-                            println!("INSERT UID = {}",show_uid);
-                        }
-                    }
+        let mut reports = Vec::new();
+        for (sink,checked) in self.sinks.iter_mut().zip(errors) {
+            let _span = tracing::info_span!("sink",sink = %sink.name()).entered();
+            let started = std::time::Instant::now();
+            let saved = sink.save(items.as_slice());
+            metrics::sink_write(self.conf.url.as_str(),sink.name().as_str(),started.elapsed().as_secs_f64(),saved.is_err());
+
+            let mut report = match saved {
+                Ok(mut r) => {
+                    r.attempts = r.attempts.max(1);
+                    r
+                }
+                Err(e) => SinkReport{
+                    sink: sink.name(),
+                    attempts: 1,
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                }
+            };
+            if let Some(e) = checked {
+                report.error = Some(match report.error {
+                    Some(saved) => format!("{}; {}",saved,e),
+                    None => e
                 });
             }
+            reports.push(report);
+        }
+        (new_items,reports)
+    }
+}
 
-            _ => {
-                let data = self.parser.parse_json()?;
-                let stdout = std::io::stdout();
-                let mut writer = std::io::BufWriter::new(stdout);
-                writer.write_all(&mut data.as_bytes())?;
-            }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::{item, serve, MemorySink};
+
+    struct NoSource;
+
+    impl Source for NoSource{
+        fn fetch(&mut self)->Result<Vec<Item>,std::io::Error>{
+            Ok(Vec::new())
         }
+    }
 
-        Ok(())
+    #[test]
+    fn failed_stored_check_keeps_other_sinks(){
+        let conf:CliConfig = serde_json::from_str(r#"{"url":""}"#).unwrap();
+        let down = MemorySink{ failures: u32::MAX, ..Default::default() };
+        let down_checks = down.checks.clone();
+        let up = MemorySink::default();
+        up.uids.borrow_mut().push(String::from("old"));
+        let up_uids = up.uids.clone();
+
+        let mut app = App::new(&conf,Box::new(NoSource),vec![Box::new(down),Box::new(up)]);
+        let items:Vec<Item> = ["old","new"].iter().map(|uid| Item{ uid: uid.to_string(), ..item("","") }).collect();
+        let reports = app.save(items).unwrap();
+
+        // Asked once, then every item is new for it.
+        assert_eq!(down_checks.get(),1);
+        assert_eq!(reports[0].saved,2);
+        assert!(reports[0].error.as_ref().unwrap().contains("stored check: sink down"));
+        assert_eq!(reports[1].saved,2);
+        assert_eq!(reports[1].error,None);
+        assert_eq!(*up_uids.borrow(),vec!["old","old","new"]);
     }
-}
\ No newline at end of file
+
+
+    #[test]
+    fn article_charset_is_detected(){
+        let conf:CliConfig = serde_json::from_str(r#"{"url":"","charset":"gbk"}"#).unwrap();
+        let mut app = App::new(&conf,Box::new(NoSource),Vec::new());
+        let url = serve("text/html; charset=utf-8","<p>文章正文</p>".as_bytes());
+        assert_eq!(app.pages.get_html(url.as_str(),"http").unwrap(),"<p>文章正文</p>");
+        assert_eq!(app.pages.charset(),"UTF-8");
+    }
+}
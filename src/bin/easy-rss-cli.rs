@@ -1,34 +1,590 @@
 extern crate easy_rss_lib;
 
 
+use clap::{Args, Parser, Subcommand};
 use easy_rss_lib::config::*;
 use easy_rss_lib::app::*;
+use easy_rss_lib::classify::Classifier;
+use easy_rss_lib::cluster::ClusterIndex;
+use easy_rss_lib::filter::Filter;
+use easy_rss_lib::health::{FeedHealth, HealthStore};
+use easy_rss_lib::item;
+use easy_rss_lib::logging::{self, LogFormat};
+use easy_rss_lib::metrics;
 
 
-fn print_help(){
-    println!("{}", r#"
-Usage: easy-rss-cli [Option]
-Option:
-    [filename]: config file [json]
+#[derive(Parser)]
+#[command(name = "easy-rss-cli", version, about = "Request RSS feeds and save the items.")]
+struct Cli{
+    #[command(flatten)]
+    overrides: Overrides,
 
-Example:
-    easy-rss-cli zhihu.json # Request to get RSS data
-"#)
+    /// Directory of the feed run history
+    #[arg(long, global = true, env = "EASY_RSS_STATE_DIR", default_value = "/var/lib/easy-rss")]
+    state_dir: String,
+
+    /// Include/exclude rules applied to every feed after its own `filters`
+    #[arg(long, global = true, env = "EASY_RSS_FILTERS")]
+    filters: Option<String>,
+
+    /// Log level or filter, e.g. debug or info,easy_rss_lib::fetch=debug
+    #[arg(long, global = true, env = "EASY_RSS_LOG_LEVEL", default_value = "info")]
+    log_level: String,
+
+    /// Log format on stderr [text, json, logfmt]
+    #[arg(long, global = true, env = "EASY_RSS_LOG_FORMAT", default_value = "text")]
+    log_format: LogFormat,
+
+    /// Write the metrics to this file after the run ( node_exporter textfile collector )
+    #[arg(long, global = true)]
+    metrics_file: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+
+#[derive(Args,Clone,Default)]
+struct Overrides{
+    /// Override `save_type` of every config [none, file, redis, mysql]
+    #[arg(long, global = true)]
+    save_type: Option<SaveType>,
+
+    /// Override `driver_url` of every config
+    #[arg(long, global = true)]
+    driver_url: Option<String>,
+
+    /// Override `table_name` of every config
+    #[arg(long, global = true)]
+    table_name: Option<String>,
+
+    /// Override `append_date` of every config
+    #[arg(long, global = true)]
+    append_date: Option<String>,
+}
+
+
+#[derive(Subcommand)]
+enum Command{
+    /// Request the feeds and save the new items
+    Run{
+        /// Also run feeds that are backing off or disabled
+        #[arg(long)]
+        force: bool,
+
+        /// Config files or conf.d-style directories
+        #[arg(required = true)]
+        configs: Vec<String>,
+    },
+
+    /// Run the feeds every --interval seconds and serve /metrics
+    Daemon{
+        /// Address of the metrics endpoint
+        #[arg(long, default_value = "127.0.0.1:9464")]
+        listen: String,
+
+        /// Seconds between two rounds, feeds backing off are skipped
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+
+        #[arg(required = true)]
+        configs: Vec<String>,
+    },
+
+    /// Parse and check the configs without network access
+    Validate{
+        #[arg(required = true)]
+        configs: Vec<String>,
+    },
+
+    /// Request the feeds, with --dry-run the parsed items are printed instead of saved
+    Fetch{
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(required = true)]
+        configs: Vec<String>,
+    },
+
+    /// List the configured feeds
+    List{
+        #[arg(required = true)]
+        configs: Vec<String>,
+    },
+
+    /// Run history of the feeds, every recorded feed without configs
+    Status{
+        configs: Vec<String>,
+    },
+
+    /// Clear the run history of the feeds, which enables disabled feeds again
+    Reset{
+        #[arg(required = true)]
+        configs: Vec<String>,
+    },
+
+    /// Print every saved item of the feeds as a JSON array, e.g. to label them for `train`
+    Export{
+        #[arg(required = true)]
+        configs: Vec<String>,
+    },
+
+    /// Train the classifier model from JSON arrays of items with a `category`
+    Train{
+        /// Model file, created or extended
+        #[arg(long)]
+        model: String,
+
+        /// Start from an empty model instead of extending the file
+        #[arg(long)]
+        reset: bool,
+
+        /// Exported item files, items without a category are skipped
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
+    /// Print a saved item by its uid
+    Show{
+        uid: String,
+
+        #[arg(required = true)]
+        configs: Vec<String>,
+    },
+}
+
+
+const COMMANDS:[&str;11] = ["run", "daemon", "validate", "fetch", "list", "status", "reset", "export", "train", "show", "help"];
+
+
+fn throw_err(e:&str)->Box<dyn std::error::Error>{
+    Box::new(std::io::Error::other(e))
+}
+
+
+// --save-type replaces `outputs` by the single output, the other flags apply to every output.
+fn apply(overrides:&Overrides,mut conf:CliConfig)->Result<CliConfig,std::io::Error>{
+    if let Some(save_type) = &overrides.save_type {
+        conf.save_type = save_type.clone();
+        conf.outputs.clear();
+    }
+    if let Some(driver_url) = &overrides.driver_url {
+        conf.driver_url = driver_url.clone();
+        conf.outputs.iter_mut().for_each(|o| o.driver_url = driver_url.clone());
+    }
+    if let Some(table_name) = &overrides.table_name {
+        conf.table_name = table_name.clone();
+        conf.outputs.iter_mut().for_each(|o| o.table_name = table_name.clone());
+    }
+    if let Some(append_date) = &overrides.append_date {
+        conf.append_date = append_date.clone();
+        conf.outputs.iter_mut().for_each(|o| o.append_date = append_date.clone());
+    }
+
+    for output in conf.outputs().iter() {
+        if let Err(key) = output.check() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,format!("Failed by Config[{}].",key)));
+        }
+    }
+    Ok(conf)
+}
+
+
+fn describe_outputs(conf:&CliConfig)->String{
+    conf.outputs().iter()
+        .map(|o| if !o.tags.is_empty() {
+            format!("{:?}:{}[{}]",o.save_type,o.table_name,o.tags.join("|"))
+        }else{
+            format!("{:?}:{}",o.save_type,o.table_name)
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+
+fn load_configs(paths:&[String],overrides:&Overrides)->Vec<(String,Result<CliConfig,std::io::Error>)>{
+    let mut list = Vec::new();
+    paths.iter().for_each(|path|{
+        if std::path::Path::new(path.as_str()).is_dir() {
+            match CliConfig::from_dir(path.as_str()) {
+                Ok(confs) => list.extend(confs),
+                Err(e) => list.push((path.clone(),Err(e)))
+            }
+        }else{
+            list.push((path.clone(),CliConfig::from(path.as_str())));
+        }
+    });
+
+    list.into_iter()
+        .map(|(filename,conf)|{
+            let conf = conf.and_then(|c|{
+                apply(overrides,c)
+                    .map_err(|e| std::io::Error::new(e.kind(),format!("{}: {}",filename,e)))
+            });
+            (filename,conf)
+        })
+        .collect()
+}
+
+
+fn format_time(timestamp:i64)->String{
+    if timestamp <= 0 {
+        return String::from("-");
+    }
+    chrono::DateTime::from_timestamp(timestamp,0)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| String::from("-"))
+}
+
+
+fn print_status(health:&FeedHealth){
+    println!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}ms\t{}\t{}",
+        health.config,health.state(),health.consecutive_failures,format_time(health.last_success),
+        health.http_status,health.items_parsed,health.items_filtered,health.new_items,health.duration_ms,health.charset,
+        health.last_error.replace('\n'," ")
+    );
+}
+
+
+// Runs one feed and records the run, Ok(false) when it was skipped by its backoff.
+fn run_feed(filename:&str,conf:&CliConfig,filter:&Filter,state_dir:&str,store:&HealthStore,force:bool)->Result<bool,std::io::Error>{
+    let _span = tracing::info_span!("feed",config = %filename,url = %conf.url,outputs = %describe_outputs(conf)).entered();
+    let mut health = store.load(filename);
+    health.url = conf.url.clone();
+    let now = chrono::Local::now().timestamp();
+    metrics::feed_health(&health);
+    if !force && !health.due(now) {
+        if health.disabled {
+            tracing::warn!(failures = health.consecutive_failures,"disabled, see `easy-rss-cli status`");
+        }else{
+            tracing::info!(until = %format_time(health.next_run),"backing off");
+        }
+        return Ok(false);
+    }
+
+    let started = std::time::Instant::now();
+    let mut app = App::from(conf);
+    app.add_filter(filter.clone());
+    let result = if conf.cluster.is_some() {
+        ClusterIndex::new(state_dir).and_then(|index| app.set_cluster_index(index).run())
+    }else{
+        app.run()
+    };
+
+    health.disabled = false;
+    health.items_parsed = 0;
+    health.items_filtered = 0;
+    health.items_suppressed = 0;
+    health.new_items = 0;
+    health.duration_ms = started.elapsed().as_millis() as u64;
+    health.http_status = app.status();
+    health.charset = app.charset();
+
+    let result = result.and_then(|report|{
+        health.items_parsed = report.items;
+        health.items_filtered = report.filtered;
+        health.items_suppressed = report.suppressed;
+        health.new_items = report.new_items;
+        tracing::info!(
+            charset = %health.charset,status = health.http_status,items = report.items,filtered = report.filtered,
+            suppressed = report.suppressed,new_items = report.new_items,
+            "fetched"
+        );
+
+        let mut errors = Vec::new();
+        for sink in report.sinks.iter() {
+            match &sink.error {
+                None if sink.failed > 0 => {
+                    tracing::error!(sink = %sink.sink,target = %sink.target,saved = sink.saved,failed = sink.failed,"Failed by save");
+                    errors.push(format!("{}: {} items failed",sink.sink,sink.failed));
+                }
+                None => tracing::info!(
+                    sink = %sink.sink,target = %sink.target,saved = sink.saved,attempts = sink.attempts,
+                    "saved"
+                ),
+                Some(e) => {
+                    tracing::error!(sink = %sink.sink,"Failed by save: {}",e);
+                    errors.push(format!("{}: {}",sink.sink,e));
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(std::io::Error::other(errors.join("; ")));
+        }
+        Ok(())
+    });
+
+    match &result {
+        Ok(_) => health.success(now),
+        Err(e) => {
+            health.failure(now,e.to_string().as_str(),&conf.health);
+            if health.disabled {
+                tracing::warn!(failures = health.consecutive_failures,"disabled");
+            }
+        }
+    }
+
+    metrics::feed_health(&health);
+    if let Err(e) = store.save(&health) {
+        tracing::warn!("Failed by save status: {}",e);
+    }
+    result.map(|_| true)
 }
 
 
 fn main()->Result<(),Box<dyn std::error::Error>> {
-    // File
-    let args:Vec<String> = std::env::args().collect();
-    if args.len() <= 1 {
-        print_help();
-        return Err(Box::new(std::io::Error::new(std::io::ErrorKind::NotFound,"Not Found Config")));
+    // `easy-rss-cli zhihu.json` is kept as a shortcut for `easy-rss-cli run zhihu.json`
+    let mut args:Vec<String> = std::env::args().collect();
+    if args.len() > 1 && !args[1].starts_with('-') && !COMMANDS.contains(&args[1].as_str()) {
+        args.insert(1,String::from("run"));
+    }
+    let cli = Cli::parse_from(args);
+    logging::init(cli.log_level.as_str(),cli.log_format)?;
+
+    let store = HealthStore::new(cli.state_dir.as_str());
+    let filter = match &cli.filters {
+        Some(path) => Filter::load(path.as_str())?,
+        None => Filter::default()
+    };
+    let mut failed = 0;
+    match &cli.command {
+        Command::Run{ configs, force } => {
+            for (filename,conf) in load_configs(configs,&cli.overrides) {
+                let conf = match conf {
+                    Ok(c) => c,
+                    Err(e) => {
+                        tracing::error!("{}",e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                if let Err(e) = run_feed(filename.as_str(),&conf,&filter,cli.state_dir.as_str(),&store,*force) {
+                    tracing::error!(config = %filename,"{}",e);
+                    failed += 1;
+                }
+            }
+        }
+
+        Command::Daemon{ listen, interval, configs } => {
+            metrics::serve(listen.as_str())?;
+            tracing::info!("Serving metrics on http://{}/metrics",listen);
+
+            // Configs are loaded every round, so edits apply without a restart.
+            loop {
+                for (filename,conf) in load_configs(configs,&cli.overrides) {
+                    let conf = match conf {
+                        Ok(c) => c,
+                        Err(e) => {
+                            tracing::error!("{}",e);
+                            continue;
+                        }
+                    };
+                    if let Err(e) = run_feed(filename.as_str(),&conf,&filter,cli.state_dir.as_str(),&store,false) {
+                        tracing::error!(config = %filename,"{}",e);
+                    }
+                }
+
+                if let Some(path) = &cli.metrics_file {
+                    if let Err(e) = metrics::write_textfile(path.as_str()) {
+                        tracing::warn!("Failed by write metrics {}: {}",path,e);
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_secs(*interval));
+            }
+        }
+
+        Command::Fetch{ configs, dry_run: false } => {
+            for (filename,conf) in load_configs(configs,&cli.overrides) {
+                let conf = match conf {
+                    Ok(c) => c,
+                    Err(e) => {
+                        tracing::error!("{}",e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                if let Err(e) = run_feed(filename.as_str(),&conf,&filter,cli.state_dir.as_str(),&store,true) {
+                    tracing::error!(config = %filename,"{}",e);
+                    failed += 1;
+                }
+            }
+        }
+
+        Command::Fetch{ configs, dry_run: true } => {
+            for (filename,conf) in load_configs(configs,&cli.overrides) {
+                let conf = match conf {
+                    Ok(c) => c,
+                    Err(e) => {
+                        tracing::error!("{}",e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                let mut app = App::from(&conf);
+                app.add_filter(filter.clone());
+                if conf.cluster.is_some() {
+                    // Only `run` saves the index, a dry run leaves it as it is.
+                    match ClusterIndex::new(cli.state_dir.as_str()) {
+                        Ok(index) => app.set_cluster_index(index),
+                        Err(e) => {
+                            tracing::error!(config = %filename,"Failed by load clusters: {}",e);
+                            failed += 1;
+                            continue;
+                        }
+                    };
+                }
+                match app.fetch() {
+                    Ok(items) => {
+                        let mut feed = json::JsonValue::new_object();
+                        feed["config"] = filename.clone().into();
+                        feed["url"] = conf.url.clone().into();
+                        feed["charset"] = app.charset().into();
+                        feed["items"] = json::parse(item::to_json_string(items.as_slice()).as_str())
+                            .unwrap_or(json::JsonValue::new_array());
+                        println!("{}",feed.dump());
+                    }
+                    Err(e) => {
+                        tracing::error!(config = %filename,"{}",e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+
+        Command::Validate{ configs } => {
+            for (filename,conf) in load_configs(configs,&cli.overrides) {
+                match conf {
+                    Ok(_) => println!("{}: OK",filename),
+                    Err(e) => {
+                        tracing::error!("{}",e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+
+        Command::List{ configs } => {
+            for (filename,conf) in load_configs(configs,&cli.overrides) {
+                match conf {
+                    Ok(c) => println!(
+                        "{}\t{}\t{}\t{}",
+                        filename,c.url,describe_outputs(&c),store.load(filename.as_str()).state()
+                    ),
+                    Err(e) => {
+                        tracing::error!("{}",e);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+
+        Command::Status{ configs } => {
+            println!("config\tstate\tfailures\tlast_success\tstatus\titems\tfiltered\tnew\tduration\tcharset\tlast_error");
+            if configs.is_empty() {
+                store.all()?.iter().for_each(print_status);
+            }
+            for (filename,conf) in load_configs(configs,&cli.overrides) {
+                if let Err(e) = conf {
+                    tracing::error!("{}",e);
+                }
+                print_status(&store.load(filename.as_str()));
+            }
+        }
+
+        Command::Reset{ configs } => {
+            for (filename,_) in load_configs(configs,&cli.overrides) {
+                if let Err(e) = store.reset(filename.as_str()) {
+                    tracing::error!(config = %filename,"{}",e);
+                    failed += 1;
+                }
+            }
+        }
+
+        Command::Export{ configs } => {
+            let mut items = Vec::new();
+            for (filename,conf) in load_configs(configs,&cli.overrides) {
+                let conf = match conf {
+                    Ok(c) => c,
+                    Err(e) => {
+                        tracing::error!("{}",e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                match App::from(&conf).export() {
+                    Ok(list) => items.extend(list),
+                    Err(e) => {
+                        tracing::error!(config = %filename,"{}",e);
+                        failed += 1;
+                    }
+                }
+            }
+            println!("{}",item::to_json_string(items.as_slice()));
+        }
+
+        Command::Train{ model, reset, files } => {
+            let mut classifier = if *reset || !std::path::Path::new(model.as_str()).exists() {
+                Classifier::default()
+            }else{
+                Classifier::load(model.as_str())?
+            };
+
+            let mut trained = 0;
+            for filename in files.iter() {
+                let data = std::fs::read_to_string(filename)?;
+                for val in item::from_json_string(data.as_str()) {
+                    if !val.category.is_empty() {
+                        classifier.train(&val,val.category.as_str());
+                        trained += 1;
+                    }
+                }
+            }
+
+            if trained == 0 {
+                return Err(throw_err("No item with a category to train"));
+            }
+            classifier.save(model.as_str())?;
+            tracing::info!(items = trained,model = %model,"trained");
+            for (category,docs) in classifier.categories() {
+                println!("{}\t{}",category,docs);
+            }
+        }
+
+        Command::Show{ uid, configs } => {
+            for (filename,conf) in load_configs(configs,&cli.overrides) {
+                let conf = match conf {
+                    Ok(c) => c,
+                    Err(e) => {
+                        tracing::error!("{}",e);
+                        continue;
+                    }
+                };
+                match App::from(&conf).show(uid.as_str()) {
+                    Ok(Some(val)) => {
+                        let mut obj = val.to_json();
+                        obj["uid"] = uid.clone().into();
+                        obj["config"] = filename.clone().into();
+                        println!("{}",json::stringify_pretty(obj,2));
+                        return Ok(());
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::error!(config = %filename,"{}",e)
+                }
+            }
+            return Err(throw_err(format!("Not Found UID = {}",uid).as_str()));
+        }
     }
 
-    let filename = args.last().ok_or(std::io::Error::new(std::io::ErrorKind::NotFound,"Not Found Config"))?;
-    // Config
-    let mut conf = CliConfig::from(filename.as_str())?;
+    if let Some(path) = &cli.metrics_file {
+        if let Err(e) = metrics::write_textfile(path.as_str()) {
+            tracing::warn!("Failed by write metrics {}: {}",path,e);
+        }
+    }
 
-    App::from(&mut conf).run()?;
+    if failed > 0 {
+        return Err(throw_err(format!("{} config(s) failed",failed).as_str()));
+    }
     Ok(())
 }
@@ -1,9 +1,14 @@
 extern crate easy_rss_lib;
 
 use easy_rss_lib::config::*;
+use easy_rss_lib::logging::{self, LogFormat};
+use easy_rss_lib::metrics;
 use easy_rss_lib::output::*;
 
 fn main()->Result<(),Box<dyn std::error::Error>> {
+    let level = std::env::var("EASY_RSS_LOG_LEVEL").unwrap_or(String::from("info"));
+    let format = std::env::var("EASY_RSS_LOG_FORMAT").unwrap_or(String::from("text"));
+    logging::init(level.as_str(),format.parse::<LogFormat>()?)?;
 
     // File
     let args:Vec<String> = std::env::args().collect();
@@ -16,7 +21,14 @@ fn main()->Result<(),Box<dyn std::error::Error>> {
     // Config
     let conf = OutputConfig::from(filename.as_str())?;
 
-    Output::from(&conf).run()?;
+    let ret = Output::from(&conf).run();
 
+    if !conf.metrics_path.is_empty() {
+        if let Err(e) = metrics::write_textfile(conf.metrics_path.as_str()) {
+            tracing::warn!("Failed by write metrics {}: {}",conf.metrics_path,e);
+        }
+    }
+
+    ret?;
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,145 @@
+use headless_chrome::protocol::cdp::types::Event;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+
+pub struct ChromeFetcher{
+    // The browser process lives as long as the tab is in use.
+    _browser: headless_chrome::Browser,
+    tab: Arc<headless_chrome::Tab>,
+    // Redirects of the page request since the last navigation.
+    redirects: Arc<AtomicUsize>,
+    max_redirects: Option<usize>,
+}
+
+impl ChromeFetcher{
+
+    pub fn launch(chrome_path:&str,sandbox:bool,idle_browser_timeout:std::time::Duration,proxy:&str)->Result<Self,std::io::Error>{
+        let path = if !chrome_path.is_empty() {
+            Some(std::path::PathBuf::from(chrome_path))
+        }else{
+            None
+        };
+
+        let options = match headless_chrome::LaunchOptionsBuilder::default()
+            .path(path)
+            .headless(true)
+            .sandbox(sandbox)
+            .idle_browser_timeout(idle_browser_timeout)
+            .proxy_server(if !proxy.is_empty() { Some(proxy) } else { None })
+            .build() {
+            Ok(o) => o,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,e))
+        };
+
+        let browser = match headless_chrome::Browser::new(options) {
+            Ok(b) => b,
+            Err(e) => return Err(std::io::Error::other(e.to_string()))
+        };
+
+        let tab = match browser.new_tab() {
+            Ok(t) => t,
+            Err(e) => return Err(std::io::Error::other(e.to_string()))
+        };
+
+        Ok(Self{ _browser: browser, tab, redirects: Arc::new(AtomicUsize::new(0)), max_redirects: None })
+    }
+
+
+    // `timeout` bounds every navigation, more than `max_redirects` redirects of the page fail it.
+    pub fn set_limits(&mut self,timeout:Option<std::time::Duration>,max_redirects:usize)->Result<(),std::io::Error>{
+        if let Some(timeout) = timeout {
+            self.tab.set_default_timeout(timeout);
+        }
+
+        let enabled = self.tab.call_method(headless_chrome::protocol::cdp::Network::Enable{
+            max_total_buffer_size: None,
+            max_resource_buffer_size: None,
+            max_post_data_size: None,
+            report_direct_socket_traffic: None,
+            enable_durable_messages: None,
+        });
+        if let Err(e) = enabled {
+            return Err(std::io::Error::other(e.to_string()));
+        }
+
+        let redirects = self.redirects.clone();
+        let listener = self.tab.add_event_listener(Arc::new(move |event:&Event|{
+            if let Event::NetworkRequestWillBeSent(sent) = event {
+                // The navigation request of the page is the one whose id is the loader id.
+                if sent.params.redirect_response.is_some() && sent.params.request_id == sent.params.loader_id {
+                    redirects.fetch_add(1,Ordering::SeqCst);
+                }
+            }
+        }));
+        if let Err(e) = listener {
+            return Err(std::io::Error::other(e.to_string()));
+        }
+        self.max_redirects = Some(max_redirects);
+        Ok(())
+    }
+
+
+    fn navigate(&self,url:&str)->Result<(),std::io::Error>{
+        self.redirects.store(0,Ordering::SeqCst);
+        if let Err(e) = self.tab.navigate_to(url).and_then(|tab| tab.wait_until_navigated()) {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()));
+        }
+
+        let redirects = self.redirects.load(Ordering::SeqCst);
+        match self.max_redirects {
+            Some(max) if redirects > max => Err(std::io::Error::other(
+                format!("Failed by request {}: {} redirects, max_redirects is {}.",url,redirects,max)
+            )),
+            _ => Ok(())
+        }
+    }
+
+
+    // The user agent and extra headers apply to the whole tab.
+    pub fn set_headers(&self,user_agent:&str,headers:&[(String,String)])->Result<(),std::io::Error>{
+        if !user_agent.is_empty() {
+            if let Err(e) = self.tab.set_user_agent(user_agent,None,None) {
+                return Err(std::io::Error::other(e.to_string()));
+            }
+        }
+
+        if !headers.is_empty() {
+            let extra = headers.iter()
+                .map(|(k,v)| (k.as_str(),v.as_str()))
+                .collect::<std::collections::HashMap<&str,&str>>();
+            if let Err(e) = self.tab.set_extra_http_headers(extra) {
+                return Err(std::io::Error::other(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+
+    pub fn get_html(&self,url:&str)->Result<String,std::io::Error>{
+        self.navigate(url)?;
+
+        match self.tab.get_content() {
+            Ok(html) => Ok(html),
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
+        }
+    }
+
+
+    // The rendered document is serialized back to markup, so XML feeds behind
+    // JS/anti-bot checks come back as XML once the browser reaches them.
+    pub fn get_xml(&self,url:&str)->Result<String,std::io::Error>{
+        self.navigate(url)?;
+
+        let serialized = match self.tab.evaluate("new XMLSerializer().serializeToString(document)",false) {
+            Ok(ret) => ret.value.and_then(|v| v.as_str().map(|s| s.to_string())),
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
+        };
+
+        match serialized {
+            Some(xml) => Ok(xml),
+            None => self.tab.get_content()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
+        }
+    }
+}
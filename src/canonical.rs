@@ -0,0 +1,161 @@
+use crate::config::CanonicalConfig;
+
+
+fn on_host(host:&str,pattern:&str)->bool{
+    host == pattern || host.ends_with(format!(".{}",pattern).as_str())
+}
+
+
+// One spelling per link: relative links are resolved against the feed url, tracking parameters,
+// fragments and trailing slashes are dropped and the parameters are sorted, `force_https` makes http links https.
+pub struct Canonicalizer{
+    conf: CanonicalConfig,
+    base: Option<url::Url>,
+}
+
+impl Canonicalizer{
+
+    pub fn new(conf:&CanonicalConfig,feed_url:&str)->Self{
+        Self{
+            conf: conf.clone(),
+            base: url::Url::parse(feed_url).ok(),
+        }
+    }
+
+
+    fn parse(&self,link:&str)->Option<url::Url>{
+        let link = link.trim();
+        match &self.base {
+            Some(base) => base.join(link).ok(),
+            None => url::Url::parse(link).ok()
+        }
+        .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+    }
+
+
+    fn stripped(&self,name:&str)->bool{
+        self.conf.strip_params.iter().any(|p| match p.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == p
+        })
+    }
+
+
+    // The target of a redirector with a `param`, e.g. https://link.zhihu.com/?target=https%3A//example.com/
+    fn unwrap(&self,url:&url::Url)->Option<url::Url>{
+        let host = url.host_str()?;
+        let redirector = self.conf.redirectors.iter()
+            .find(|r| !r.param.is_empty() && on_host(host,r.host.as_str()))?;
+        let target = url.query_pairs()
+            .find(|(k,_)| k == redirector.param.as_str())
+            .map(|(_,v)| v.to_string())?;
+        // A `javascript:` or `data:` target is no link to keep.
+        url::Url::parse(target.as_str()).ok()
+            .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+    }
+
+
+    // Whether the link is on a redirector that has to be requested to know the target.
+    pub fn follow(&self,link:&str)->bool{
+        match self.parse(link).and_then(|u| u.host_str().map(|h| h.to_string())) {
+            Some(host) => self.conf.redirectors.iter().any(|r| r.param.is_empty() && on_host(host.as_str(),r.host.as_str())),
+            None => false
+        }
+    }
+
+
+    // Links that are no http(s) url are kept as they are.
+    pub fn canonical(&self,link:&str)->String{
+        let mut url = match self.parse(link) {
+            Some(u) => u,
+            None => return link.to_string()
+        };
+        if let Some(target) = self.unwrap(&url) {
+            url = target;
+        }
+
+        url.set_fragment(None);
+        if self.conf.force_https && url.scheme() == "http" {
+            let _ = url.set_scheme("https");
+        }
+
+        let mut pairs:Vec<(String,String)> = url.query_pairs()
+            .filter(|(k,_)| !self.stripped(k))
+            .map(|(k,v)| (k.to_string(),v.to_string()))
+            .collect();
+        if self.conf.sort_params {
+            pairs.sort();
+        }
+        if !pairs.is_empty() {
+            url.query_pairs_mut().clear().extend_pairs(pairs.iter());
+        }else{
+            url.set_query(None);
+        }
+
+        if self.conf.strip_trailing_slash && url.path().len() > 1 && url.path().ends_with('/') {
+            let path = url.path().trim_end_matches('/').to_string();
+            url.set_path(if !path.is_empty() { path.as_str() } else { "/" });
+        }
+        url.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn canonical(link:&str)->String{
+        Canonicalizer::new(&CanonicalConfig::default(),"http://example.com/feed/rss.xml").canonical(link)
+    }
+
+    #[test]
+    fn canonical_forms(){
+        assert_eq!(canonical("http://example.com/a/?utm_source=rss&b=2&a=1&from=feed#top"),"http://example.com/a?a=1&b=2");
+        assert_eq!(canonical("https://example.com/a?fbclid=x"),"https://example.com/a");
+        assert_eq!(canonical("https://example.com/"),"https://example.com/");
+        assert_eq!(canonical("  https://EXAMPLE.com/a//  "),"https://example.com/a");
+    }
+
+    #[test]
+    fn relative_links(){
+        assert_eq!(canonical("/post/1"),"http://example.com/post/1");
+        assert_eq!(canonical("post/2?spm=1"),"http://example.com/feed/post/2");
+        assert_eq!(canonical("mailto:me@example.com"),"mailto:me@example.com");
+        assert_eq!(Canonicalizer::new(&CanonicalConfig::default(),"").canonical("/post/1"),"/post/1");
+    }
+
+    #[test]
+    fn redirectors(){
+        assert_eq!(canonical("https://link.zhihu.com/?target=http%3A//example.org/x%3Futm_medium%3Dzhihu"),"http://example.org/x");
+        assert_eq!(canonical("https://link.zhihu.com/?other=1"),"https://link.zhihu.com/?other=1");
+        assert_eq!(canonical("https://link.zhihu.com/?target=javascript%3Aalert(1)"),"https://link.zhihu.com/?target=javascript%3Aalert%281%29");
+
+        let canonicalizer = Canonicalizer::new(&CanonicalConfig::default(),"https://example.com/");
+        assert!(canonicalizer.follow("https://t.co/abc"));
+        assert!(canonicalizer.follow("http://www.feedproxy.google.com/~r/x"));
+        assert!(!canonicalizer.follow("https://link.zhihu.com/?target=x"));
+        assert!(!canonicalizer.follow("https://not-t.co/abc"));
+    }
+
+    #[test]
+    fn force_https(){
+        let conf = CanonicalConfig{ force_https: true, ..Default::default() };
+        let canonicalizer = Canonicalizer::new(&conf,"http://example.com/feed/rss.xml");
+        assert_eq!(canonicalizer.canonical("/post/1"),"https://example.com/post/1");
+        assert_eq!(canonicalizer.canonical("https://link.zhihu.com/?target=http%3A//example.org/x"),"https://example.org/x");
+    }
+
+    #[test]
+    fn disabled_rules(){
+        let conf = CanonicalConfig{
+            strip_params: Vec::new(),
+            force_https: false,
+            strip_trailing_slash: false,
+            sort_params: false,
+            redirectors: Vec::new(),
+        };
+        let canonicalizer = Canonicalizer::new(&conf,"");
+        assert_eq!(canonicalizer.canonical("http://example.com/a/?utm_source=x&b=1"),"http://example.com/a/?utm_source=x&b=1");
+    }
+}
@@ -0,0 +1,229 @@
+use crate::item::Item;
+use crate::throw_err;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+
+fn is_cjk(c:char)->bool{
+    matches!(c as u32,
+        0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F |
+        0x3040..=0x30FF | 0xAC00..=0xD7AF
+    )
+}
+
+
+fn strip_tags(html:&str)->String{
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+
+// Overlapping bigrams of CJK runs ( a lone character stays a token ) and lowercase words of the rest,
+// so no dictionary is needed to segment Chinese text.
+pub fn tokenize(text:&str)->Vec<String>{
+    let mut tokens = Vec::new();
+    let mut run:Vec<char> = Vec::new();
+    let mut word = String::new();
+
+    let flush_run = |run:&mut Vec<char>,tokens:&mut Vec<String>|{
+        if run.len() == 1 {
+            tokens.push(run[0].to_string());
+        }
+        run.windows(2).for_each(|w| tokens.push(w.iter().collect()));
+        run.clear();
+    };
+    let flush_word = |word:&mut String,tokens:&mut Vec<String>|{
+        if word.chars().count() > 1 {
+            tokens.push(word.clone());
+        }
+        word.clear();
+    };
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            flush_word(&mut word,&mut tokens);
+            run.push(c);
+        }else if c.is_alphanumeric() {
+            flush_run(&mut run,&mut tokens);
+            word.extend(c.to_lowercase());
+        }else{
+            flush_run(&mut run,&mut tokens);
+            flush_word(&mut word,&mut tokens);
+        }
+    }
+    flush_run(&mut run,&mut tokens);
+    flush_word(&mut word,&mut tokens);
+    tokens
+}
+
+
+pub(crate) fn item_tokens(item:&Item)->Vec<String>{
+    tokenize(format!("{} {}",item.title,strip_tags(item.description.as_str())).as_str())
+}
+
+
+#[derive(Debug,Clone,Default,Serialize,Deserialize)]
+struct Class{
+    docs: u64,
+    tokens: u64,
+    counts: BTreeMap<String,u64>,
+}
+
+
+// Multinomial naive Bayes with add-one smoothing over `tokenize` of title and description.
+#[derive(Debug,Clone,Default,Serialize,Deserialize)]
+pub struct Classifier{
+    classes: BTreeMap<String,Class>,
+    vocabulary: u64,
+}
+
+impl Classifier{
+
+    pub fn load(filename:&str)->Result<Self,std::io::Error>{
+        let data = std::fs::read_to_string(filename)?;
+        serde_json::from_str::<Self>(data.as_str())
+            .map_err(|e| throw_err(format!("{}: {}",filename,e).as_str()))
+    }
+
+
+    pub fn save(&self,filename:&str)->Result<(),std::io::Error>{
+        let data = match serde_json::to_string(self) {
+            Ok(d) => d,
+            Err(e) => return Err(throw_err(e.to_string().as_str()))
+        };
+
+        let tmp = format!("{}.tmp",filename);
+        std::fs::write(tmp.as_str(),data.as_bytes())?;
+        std::fs::rename(tmp.as_str(),filename)
+    }
+
+
+    pub fn train(&mut self,item:&Item,category:&str){
+        self.classes.entry(category.to_string()).or_default().docs += 1;
+        for token in item_tokens(item) {
+            if !self.classes.values().any(|c| c.counts.contains_key(&token)) {
+                self.vocabulary += 1;
+            }
+            let class = self.classes.get_mut(category).unwrap();
+            class.tokens += 1;
+            *class.counts.entry(token).or_insert(0) += 1;
+        }
+    }
+
+
+    // Trained items per category.
+    pub fn categories(&self)->Vec<(String,u64)>{
+        self.classes.iter().map(|(name,c)| (name.clone(),c.docs)).collect()
+    }
+
+
+    // The most likely category and its posterior probability, None before training.
+    pub fn classify(&self,item:&Item)->Option<(String,f64)>{
+        let docs:u64 = self.classes.values().map(|c| c.docs).sum();
+        if docs == 0 {
+            return None;
+        }
+
+        let tokens = item_tokens(item);
+        let scores:Vec<(&String,f64)> = self.classes.iter()
+            .map(|(name,class)|{
+                let denominator = (class.tokens + self.vocabulary + 1) as f64;
+                let likelihood:f64 = tokens.iter()
+                    .map(|t| ((class.counts.get(t).copied().unwrap_or(0) + 1) as f64 / denominator).ln())
+                    .sum();
+                (name,(class.docs as f64 / docs as f64).ln() + likelihood)
+            })
+            .collect();
+
+        let (best,top) = scores.iter()
+            .fold((None,f64::NEG_INFINITY),|(best,top),(name,score)| if *score > top {
+                (Some(*name),*score)
+            }else{
+                (best,top)
+            });
+        let total:f64 = scores.iter().map(|(_,score)| (score - top).exp()).sum();
+        best.map(|name| (name.clone(),1.0 / total))
+    }
+
+
+    // Sets `category` when the confidence reaches `min_confidence`, `confidence` is always recorded.
+    pub fn apply(&self,items:&mut [Item],min_confidence:f64){
+        for item in items.iter_mut() {
+            if let Some((category,confidence)) = self.classify(item) {
+                item.confidence = confidence;
+                if confidence >= min_confidence {
+                    item.category = category;
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::{item, temp_dir};
+
+    #[test]
+    fn tokenize_cjk_bigrams_and_words(){
+        assert_eq!(tokenize("苹果发布 New iPhone!"),vec!["苹果","果发","发布","new","iphone"]);
+        assert_eq!(tokenize("猫 a Rust"),vec!["猫","rust"]);
+        assert_eq!(item_tokens(&item("标题","<p>Body</p>")),vec!["标题","body"]);
+    }
+
+    #[test]
+    fn classify_before_training(){
+        assert_eq!(Classifier::default().classify(&item("anything","")),None);
+    }
+
+    #[test]
+    fn train_and_classify(){
+        let mut classifier = Classifier::default();
+        classifier.train(&item("Football match ends in a draw","The team scored twice"),"sport");
+        classifier.train(&item("Tennis final tonight","The champion wins the match"),"sport");
+        classifier.train(&item("New compiler release","Faster builds and better errors"),"tech");
+        classifier.train(&item("Chip maker unveils processor","The compiler team benchmarks"),"tech");
+        assert_eq!(classifier.categories(),vec![("sport".to_string(),2),("tech".to_string(),2)]);
+
+        let (category,confidence) = classifier.classify(&item("Match report","The team wins the final")).unwrap();
+        assert_eq!(category,"sport");
+        assert!(confidence > 0.5 && confidence <= 1.0);
+
+        // Unknown tokens leave both categories close, below `min_confidence` only the confidence is set.
+        let mut items = vec![item("Compiler errors","A faster release"),item("Unseen words","")];
+        classifier.apply(&mut items,0.6);
+        assert_eq!(items[0].category,"tech");
+        assert!(items[1].confidence >= 0.5 && items[1].confidence < 0.6);
+        assert_eq!(items[1].category,"");
+    }
+
+    #[test]
+    fn save_and_load_round_trip(){
+        let dir = temp_dir("model");
+        let filename = dir.join("model.json");
+        let filename = filename.to_str().unwrap();
+        let mut classifier = Classifier::default();
+        classifier.train(&item("Football match","goal"),"sport");
+        classifier.train(&item("Compiler release","rust"),"tech");
+        classifier.save(filename).unwrap();
+
+        let loaded = Classifier::load(filename).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(loaded.categories(),classifier.categories());
+        let sample = item("Rust compiler","");
+        assert_eq!(loaded.classify(&sample),classifier.classify(&sample));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::classify;
+use crate::item::Item;
+use crate::throw_err;
+use serde::{Deserialize, Serialize};
+
+
+fn hash64(token:&str)->u64{
+    let digest = md5::compute(token.as_bytes());
+    let mut bytes = [0u8;8];
+    bytes.copy_from_slice(&digest.0[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+
+// SimHash of the title and description tokens weighted by their count, None without any token.
+pub fn simhash(item:&Item)->Option<u64>{
+    let mut counts = std::collections::HashMap::new();
+    for token in classify::item_tokens(item) {
+        *counts.entry(token).or_insert(0i64) += 1;
+    }
+    if counts.is_empty() {
+        return None;
+    }
+
+    let mut weights = [0i64;64];
+    for (token,count) in counts.iter() {
+        let hash = hash64(token.as_str());
+        for (bit,weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += count;
+            }else{
+                *weight -= count;
+            }
+        }
+    }
+
+    Some(weights.iter().enumerate().fold(0u64,|hash,(bit,weight)| if *weight > 0 {
+        hash | 1 << bit
+    }else{
+        hash
+    }))
+}
+
+
+#[derive(Debug,Clone,Serialize,Deserialize)]
+struct Entry{
+    uid: String,
+    fingerprint: u64,
+    cluster: String,
+    seen: i64,
+}
+
+
+// Fingerprints of the items seen by every feed, `<state_dir>/clusters.json`.
+// Without a path nothing is written, e.g. for embedders.
+#[derive(Debug,Clone,Default)]
+pub struct ClusterIndex{
+    path: Option<std::path::PathBuf>,
+    entries: Vec<Entry>,
+}
+
+impl ClusterIndex{
+
+    pub fn new(state_dir:&str)->Result<Self,std::io::Error>{
+        let path = std::path::Path::new(state_dir).join("clusters.json");
+        Ok(Self{
+            entries: Self::read(&path)?,
+            path: Some(path),
+        })
+    }
+
+
+    // A missing index is empty, a corrupt one is an error instead of silently starting over.
+    fn read(path:&std::path::Path)->Result<Vec<Entry>,std::io::Error>{
+        let data = match std::fs::read_to_string(path) {
+            Ok(d) => d,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e)
+        };
+        serde_json::from_str::<Vec<Entry>>(data.as_str())
+            .map_err(|e| throw_err(format!("{}: {} ( remove it to start a new index )",path.display(),e).as_str()))
+    }
+
+
+    // The cluster of the item and whether an other item founded it, the item joins the index.
+    pub fn assign(&mut self,uid:&str,fingerprint:u64,threshold:u32,now:i64)->(String,bool){
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.uid == uid) {
+            entry.seen = now;
+            entry.fingerprint = fingerprint;
+            return (entry.cluster.clone(),entry.cluster != uid);
+        }
+
+        let found = self.entries.iter()
+            .map(|e| ((e.fingerprint ^ fingerprint).count_ones(),e))
+            .filter(|(distance,_)| *distance <= threshold)
+            .min_by_key(|(distance,_)| *distance)
+            .map(|(_,e)| e.cluster.clone());
+
+        let (cluster,duplicate) = match found {
+            Some(cluster) => (cluster,true),
+            None => (uid.to_string(),false)
+        };
+        self.entries.push(Entry{
+            uid: uid.to_string(),
+            fingerprint,
+            cluster: cluster.clone(),
+            seen: now,
+        });
+        (cluster,duplicate)
+    }
+
+
+    // Entries written meanwhile by other processes are kept, entries older than `window` seconds are dropped.
+    // Feeds run as separate processes, the lock file serializes their read, merge and write.
+    pub fn save(&mut self,window:u64,now:i64)->Result<(),std::io::Error>{
+        let path = match &self.path {
+            Some(p) => p.clone(),
+            None => return Ok(())
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let lock = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.with_extension("json.lock"))?;
+        lock.lock()?;
+
+        for entry in Self::read(&path)? {
+            if !self.entries.iter().any(|e| e.uid == entry.uid) {
+                self.entries.push(entry);
+            }
+        }
+        self.entries.retain(|e| e.seen + window as i64 >= now);
+
+        let data = match serde_json::to_string(&self.entries) {
+            Ok(d) => d,
+            Err(e) => return Err(throw_err(e.to_string().as_str()))
+        };
+        let tmp = path.with_extension(format!("json.{}.tmp",std::process::id()));
+        std::fs::write(&tmp,data.as_bytes())?;
+        std::fs::rename(&tmp,&path)
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::{item, temp_dir};
+
+    #[test]
+    fn simhash_of_same_text(){
+        let a = simhash(&item("Rust 1.80 released","The Rust team ships a new stable version")).unwrap();
+        let b = simhash(&item("Rust 1.80 released","The Rust team ships a new stable version")).unwrap();
+        let c = simhash(&item("Weather tomorrow","Rain and wind in the north of the country")).unwrap();
+        assert_eq!(a,b);
+        assert!((a ^ c).count_ones() > 3);
+        assert_eq!(simhash(&item("","")),None);
+    }
+
+    #[test]
+    fn assign_within_hamming_threshold(){
+        let mut index = ClusterIndex::default();
+        assert_eq!(index.assign("a",0b0000,3,0),("a".to_string(),false));
+        // 3 bits apart joins, 4 bits apart founds a new cluster.
+        assert_eq!(index.assign("b",0b0111,3,0),("a".to_string(),true));
+        assert_eq!(index.assign("c",0b1111 << 8,3,0),("c".to_string(),false));
+        // The same uid keeps its cluster.
+        assert_eq!(index.assign("a",0b0000,3,1),("a".to_string(),false));
+        assert_eq!(index.assign("b",0b0111,0,1),("a".to_string(),true));
+    }
+
+    #[test]
+    fn assign_nearest_cluster(){
+        let mut index = ClusterIndex::default();
+        index.assign("a",0b0000,0,0);
+        index.assign("b",0b1111_0000,0,0);
+        assert_eq!(index.assign("c",0b1110_0000,3,0).0,"b");
+    }
+
+    #[test]
+    fn save_merges_and_drops_old_entries(){
+        let dir = temp_dir("cluster");
+
+        let mut first = ClusterIndex::new(dir.to_str().unwrap()).unwrap();
+        first.assign("old",1,3,0);
+        first.assign("a",2,3,100);
+        let mut second = ClusterIndex::new(dir.to_str().unwrap()).unwrap();
+        second.assign("b",4,3,100);
+        first.save(3600,100).unwrap();
+        second.save(3600,100).unwrap();
+
+        let mut uids:Vec<String> = ClusterIndex::new(dir.to_str().unwrap()).unwrap().entries.into_iter().map(|e| e.uid).collect();
+        uids.sort();
+        assert_eq!(uids,vec!["a","b","old"]);
+
+        let mut third = ClusterIndex::new(dir.to_str().unwrap()).unwrap();
+        third.save(50,100).unwrap();
+        assert_eq!(ClusterIndex::new(dir.to_str().unwrap()).unwrap().entries.len(),2);
+
+        std::fs::write(dir.join("clusters.json"),"{").unwrap();
+        assert!(ClusterIndex::new(dir.to_str().unwrap()).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,27 +1,402 @@
+use crate::loader::{self, Document};
+use crate::throw_err;
 use easy_rss::{RSS_DEFAULT_TITLE_TAG, RSS_DEFAULT_LINK_TAG, RSS_DEFAULT_AUTHOR_TAG, RSS_DEFAULT_DESC_TAG, RSS_DEFAULT_GUID_TAG, RSS_DEFAULT_PUBLISH_TAG};
+use serde::Deserialize;
 
-#[derive(Debug,Clone,PartialEq)]
+#[derive(Debug,Clone,Default,PartialEq,Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SaveType{
+    #[default]
     None,
     File,
     Redis,
     MySQL,
 }
 
-#[derive(Debug,Clone)]
+// The hand-written parser lowercased `save_type`/`output_type`, so "MySQL" keeps working.
+fn lowercase<'de,D:serde::Deserializer<'de>,T:Deserialize<'de>>(deserializer:D)->Result<T,D::Error>{
+    let value = String::deserialize(deserializer)?.to_lowercase();
+    T::deserialize(serde::de::value::StringDeserializer::<D::Error>::new(value))
+}
+
+impl std::str::FromStr for SaveType{
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(SaveType::None),
+            "file" => Ok(SaveType::File),
+            "redis" => Ok(SaveType::Redis),
+            "mysql" => Ok(SaveType::MySQL),
+            _ => Err(throw_err(format!("Failed by save_type `{}`, expected none, file, redis or mysql.",s).as_str()))
+        }
+    }
+}
+
+#[derive(Debug,Clone,Default,PartialEq,Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceType{
+    #[default]
+    RSS,
+    Scrape,
+}
+
+#[derive(Debug,Clone,Default,Deserialize)]
+#[serde(default)]
+pub struct ScrapeConfig{
+    pub item: String,
+    pub title: String,
+    pub link: String,
+    pub date: String,
+    pub author: String,
+    pub summary: String,
+}
+
+#[derive(Debug,Clone,Default,Deserialize)]
+pub struct BasicAuth{
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+// Backoff doubles from `backoff` up to `max_backoff` seconds, `jitter` adds up to that share at random.
+#[derive(Debug,Clone,Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy{
+    pub attempts: u32,
+    pub backoff: f64,
+    pub max_backoff: f64,
+    pub jitter: f64,
+    pub statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy{
+    fn default() -> Self {
+        Self{
+            attempts: 3,
+            backoff: 1.0,
+            max_backoff: 60.0,
+            jitter: 0.2,
+            statuses: vec![408,429,500,502,503,504],
+        }
+    }
+}
+
+// Client options of every request made for a feed, timeouts are in seconds and 0 keeps the default.
+#[derive(Debug,Clone,Deserialize)]
+#[serde(default)]
+pub struct HttpConfig{
+    pub user_agent: String,
+    pub referer: String,
+    pub headers: std::collections::BTreeMap<String,String>,
+    pub cookies: std::collections::BTreeMap<String,String>,
+    pub basic_auth: Option<BasicAuth>,
+    pub proxy: String,
+    pub connect_timeout: u64,
+    pub read_timeout: u64,
+    pub max_redirects: usize,
+    pub retry: RetryPolicy,
+    pub host_interval: f64,
+}
+
+impl Default for HttpConfig{
+    fn default() -> Self {
+        Self{
+            user_agent: String::new(),
+            referer: String::new(),
+            headers: Default::default(),
+            cookies: Default::default(),
+            basic_auth: None,
+            proxy: String::new(),
+            connect_timeout: 0,
+            read_timeout: 0,
+            max_redirects: 10,
+            retry: RetryPolicy::default(),
+            host_interval: 0.5,
+        }
+    }
+}
+
+// Conditions of one rule are combined, `keywords` and `regex` are searched in `fields`.
+#[derive(Debug,Clone,Deserialize)]
+#[serde(default)]
+pub struct FilterRule{
+    pub keywords: Vec<String>,
+    pub regex: String,
+    pub author: String,
+    pub domain: String,
+    pub fields: Vec<String>,
+}
+
+impl Default for FilterRule{
+    fn default() -> Self {
+        Self{
+            keywords: Vec::new(),
+            regex: String::new(),
+            author: String::new(),
+            domain: String::new(),
+            fields: vec![String::from("title"),String::from("description")],
+        }
+    }
+}
+
+#[derive(Debug,Clone,Default,Deserialize)]
+#[serde(default)]
+pub struct FilterConfig{
+    pub include: Vec<FilterRule>,
+    pub exclude: Vec<FilterRule>,
+}
+
+impl FilterConfig{
+    // A global rules file in any config format, shaped like the `filters` section of a feed.
+    pub fn from(filename:&str)->Result<Self,std::io::Error>{
+        let doc = Document::load(filename)?;
+        let conf:Self = doc.deserialize()?;
+        conf.check(&doc,"")?;
+        Ok(conf)
+    }
+
+
+    fn check(&self,doc:&Document,prefix:&str)->Result<(),std::io::Error>{
+        check_rules(doc,format!("{}include",prefix).as_str(),self.include.as_slice())?;
+        check_rules(doc,format!("{}exclude",prefix).as_str(),self.exclude.as_slice())
+    }
+}
+
+fn check_rules(doc:&Document,path:&str,rules:&[FilterRule])->Result<(),std::io::Error>{
+    for (i,rule) in rules.iter().enumerate() {
+        let path = format!("{}[{}]",path,i);
+        if let Some(f) = rule.fields.iter().find(|f| !FILTER_FIELDS.contains(&f.as_str())) {
+            let message = format!("unknown field `{}`, expected one of {}",f,FILTER_FIELDS.join(", "));
+            return Err(doc.error_at(format!("{}.fields",path).as_str(),message.as_str()));
+        }
+        if !rule.regex.is_empty() {
+            if let Err(e) = regex::Regex::new(rule.regex.as_str()) {
+                return Err(doc.error_at(format!("{}.regex",path).as_str(),e.to_string().as_str()));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Rules run before `fetch_content`, so there is no `content` to match yet.
+const FILTER_FIELDS:[&str;4] = ["title", "description", "author", "link"];
+
+// Naive Bayes model trained by `easy-rss-cli train`, `category` is only set from `min_confidence` on.
+#[derive(Debug,Clone,Default,Deserialize)]
+#[serde(default)]
+pub struct ClassifierConfig{
+    pub model: String,
+    pub min_confidence: f64,
+}
+
+// Near-duplicates across feeds: fingerprints within `threshold` differing bits share a cluster,
+// fingerprints are remembered for `window` seconds.
+#[derive(Debug,Clone,Deserialize)]
+pub struct ClusterConfig{
+    #[serde(default = "default_hamming")]
+    pub threshold: u32,
+    #[serde(default)]
+    pub suppress: bool,
+    #[serde(default = "default_cluster_window")]
+    pub window: u64,
+}
+
+#[derive(Debug,Clone,Default,PartialEq,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UidFrom{
+    #[default]
+    Guid,
+    Link,
+    TitlePublish,
+    Description,
+    Template,
+}
+
+#[derive(Debug,Clone,Default,PartialEq,Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UidHash{
+    #[default]
+    MD5,
+    SHA1,
+    SHA256,
+}
+
+// What the uid of an item is the hash of, the default is the guid falling back to the link.
+// `template` joins fields like `{title}|{publish}`.
+#[derive(Debug,Clone,Default,Deserialize)]
+#[serde(default)]
+pub struct UidConfig{
+    pub from: UidFrom,
+    pub template: String,
+    pub hash: UidHash,
+}
+
+// No `content`: it is fetched for new items only, which the uid decides.
+pub const ITEM_FIELDS:[&str;6] = ["title", "link", "author", "description", "guid", "publish"];
+
+// Allowlist of the sanitized description, empty lists keep the ammonia defaults;
+// `attributes` maps a tag ( `*` for every tag ) to its allowed attributes.
+#[derive(Debug,Clone,Deserialize)]
+#[serde(default)]
+pub struct SanitizeConfig{
+    pub tags: Vec<String>,
+    pub attributes: std::collections::BTreeMap<String,Vec<String>>,
+    pub tracker_hosts: Vec<String>,
+}
+
+impl Default for SanitizeConfig{
+    fn default() -> Self {
+        Self{
+            tags: Vec::new(),
+            attributes: std::collections::BTreeMap::new(),
+            tracker_hosts: [
+                "feeds.feedburner.com", "pixel.wp.com", "stats.wordpress.com",
+                "www.google-analytics.com", "pixel.quantserve.com", "sb.scorecardresearch.com",
+            ].iter().map(|h| h.to_string()).collect(),
+        }
+    }
+}
+
+// A link on `host` ( or a subdomain ) points to the url in its `param` query parameter,
+// without `param` the redirect is followed.
+#[derive(Debug,Clone,Default,Deserialize)]
+#[serde(default)]
+pub struct Redirector{
+    pub host: String,
+    pub param: String,
+}
+
+// Rewrites `link` ( and a `guid` that is a url ) before the uid is computed, see `canonical::Canonicalizer`.
+// `strip_params` names end with `*` to match a prefix.
+#[derive(Debug,Clone,Deserialize)]
+#[serde(default)]
+pub struct CanonicalConfig{
+    pub strip_params: Vec<String>,
+    pub force_https: bool,
+    pub strip_trailing_slash: bool,
+    pub sort_params: bool,
+    pub redirectors: Vec<Redirector>,
+}
+
+impl Default for CanonicalConfig{
+    fn default() -> Self {
+        let redirector = |host:&str,param:&str| Redirector{ host: host.to_string(), param: param.to_string() };
+        Self{
+            strip_params: ["utm_*", "from", "spm", "fbclid", "gclid"].iter().map(|p| p.to_string()).collect(),
+            // Sites without https keep working links, opt in when a feed mixes both.
+            force_https: false,
+            strip_trailing_slash: true,
+            sort_params: true,
+            redirectors: vec![
+                redirector("link.zhihu.com","target"),
+                redirector("weibo.cn","u"),
+                redirector("feedproxy.google.com",""),
+                redirector("t.co",""),
+            ],
+        }
+    }
+}
+
+// After a failure the feed waits `backoff` seconds, doubled per failure up to `max_backoff`,
+// and is disabled after `max_failures` failures in a row ( 0 never disables it ).
+#[derive(Debug,Clone,Deserialize)]
+#[serde(default)]
+pub struct HealthConfig{
+    pub max_failures: u32,
+    pub backoff: u64,
+    pub max_backoff: u64,
+}
+
+impl Default for HealthConfig{
+    fn default() -> Self {
+        Self{
+            max_failures: 10,
+            backoff: 300,
+            max_backoff: 86400,
+        }
+    }
+}
+
+// One place the parsed items are saved to, retried `retries` times `retry_delay` seconds apart.
+// With `tags` only items carrying one of them are saved here.
+#[derive(Debug,Clone,Deserialize)]
+pub struct OutputTarget{
+    #[serde(deserialize_with = "lowercase")]
+    pub save_type: SaveType,
+    #[serde(default)]
+    pub driver_url: String,
+    #[serde(default)]
+    pub table_name: String,
+    #[serde(default)]
+    pub append_date: String,
+    #[serde(default)]
+    pub retries: u32,
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+// A feed file of a config directory and its config, or why it failed to load.
+pub type DirEntry = (String,Result<CliConfig,std::io::Error>);
+
+#[derive(Debug,Clone,Deserialize)]
 pub struct CliConfig{
     pub url: String,
+    #[serde(default = "default_charset")]
     pub charset: String,
+    #[serde(default)]
+    pub source_type: SourceType,
+    #[serde(default)]
+    pub scrape: ScrapeConfig,
+    #[serde(default,deserialize_with = "lowercase")]
     pub save_type: SaveType,
+    #[serde(default)]
     pub driver_url: String,
+    #[serde(default)]
     pub table_name: String,
+    #[serde(default)]
     pub append_date: String,
+    #[serde(default)]
+    pub outputs: Vec<OutputTarget>,
+
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub filters: FilterConfig,
+    #[serde(default)]
+    pub tags: std::collections::BTreeMap<String,Vec<FilterRule>>,
+    #[serde(default)]
+    pub classifier: ClassifierConfig,
+    #[serde(default)]
+    pub cluster: Option<ClusterConfig>,
+    #[serde(default)]
+    pub canonical: Option<CanonicalConfig>,
+    #[serde(default)]
+    pub uid: UidConfig,
+    #[serde(default)]
+    pub sanitize: SanitizeConfig,
+    #[serde(default = "default_fetcher")]
+    pub fetcher: String,
+    #[serde(default)]
+    pub fetch_content: String,
+    #[serde(default)]
+    pub chrome_path: String,
 
+    #[serde(default = "default_title_tag")]
     pub title_tag:String,
+    #[serde(default = "default_link_tag")]
     pub link_tag:String,
+    #[serde(default = "default_author_tag")]
     pub author_tag: String,
+    #[serde(default = "default_description_tag")]
     pub description_tag:String,
+    #[serde(default = "default_guid_tag")]
     pub guid_tag:String,
+    #[serde(default = "default_publish_tag")]
     pub publish_tag:String,
 }
 
@@ -32,6 +407,19 @@ pub enum OutputType{
     JPEG(u32),
     PNG,
     PDF,
+    MHTML,
+    WARC,
+}
+
+
+#[derive(Debug,Clone,Deserialize)]
+pub struct ChangeDetection{
+    #[serde(default)]
+    pub state_path: String,
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    #[serde(default = "default_pixel_tolerance")]
+    pub pixel_tolerance: u8,
 }
 
 
@@ -45,231 +433,254 @@ pub struct OutputConfig{
     pub window_size_height: u32,
     pub output_type: OutputType,
     pub output_path: String,
-    pub append_date: String
+    pub append_date: String,
+    pub urls: Vec<String>,
+    pub change_detection: Option<ChangeDetection>,
+    pub metrics_path: String,
 }
 
-fn throw_err(e:&str)->std::io::Error{
-    return std::io::Error::new(
-        std::io::ErrorKind::InvalidData,
-        e
-    );
-}
 
+#[derive(Debug,Clone,Default,PartialEq,Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat{
+    #[default]
+    None,
+    Jpeg,
+    Png,
+    Pdf,
+    Mhtml,
+    Warc,
+}
 
-impl CliConfig{
+#[derive(Debug,Clone,Deserialize)]
+struct OutputConfigFile{
+    chrome_path: String,
+    headless: bool,
+    sandbox: bool,
+    idle_browser_timeout: u64,
+    window_size_width: u32,
+    window_size_height: u32,
+    #[serde(default,deserialize_with = "lowercase")]
+    output_type: OutputFormat,
+    #[serde(default = "default_output_quality")]
+    output_quality: u32,
+    #[serde(default = "default_output_path")]
+    output_path: String,
+    #[serde(default)]
+    append_date: String,
+    #[serde(default = "default_urls")]
+    urls: Vec<String>,
+    #[serde(default)]
+    change_detection: Option<ChangeDetection>,
+    #[serde(default)]
+    metrics_path: String,
+}
 
-    pub fn from<'a>(filename:&str)->Result<Self,std::io::Error>{
-        let json_config = std::fs::read_to_string(filename)?;
-        let conf = match json::parse(json_config.as_str()) {
-            Ok(c)=>c,
-            Err(e) => {
-                return Err(throw_err(e.to_string().as_str()));
-            }
-        };
 
+fn default_charset()->String{ String::from("auto") }
+fn default_fetcher()->String{ String::from("http") }
+fn default_title_tag()->String{ RSS_DEFAULT_TITLE_TAG.to_string() }
+fn default_link_tag()->String{ RSS_DEFAULT_LINK_TAG.to_string() }
+fn default_author_tag()->String{ RSS_DEFAULT_AUTHOR_TAG.to_string() }
+fn default_description_tag()->String{ RSS_DEFAULT_DESC_TAG.to_string() }
+fn default_guid_tag()->String{ RSS_DEFAULT_GUID_TAG.to_string() }
+fn default_publish_tag()->String{ RSS_DEFAULT_PUBLISH_TAG.to_string() }
+fn default_retry_delay()->u64{ 1 }
+fn default_hamming()->u32{ 3 }
+fn default_cluster_window()->u64{ 7 * 86400 }
+fn default_threshold()->f64{ 0.01 }
+fn default_pixel_tolerance()->u8{ 16 }
+fn default_output_quality()->u32{ 100 }
+fn default_output_path()->String{ String::from(".") }
+fn default_urls()->Vec<String>{
+    vec![
+        String::from("https://www.baidu.com"),
+        String::from("https://cn.bing.com"),
+        String::from("https://www.meteorcat.com/#/"),
+    ]
+}
 
-        if !conf.is_object() {
-            return Err(throw_err("Failed by Config[Json File]."));
-        }
 
-        if !conf.has_key("url") && !conf["url"].is_string()  {
-            return Err(throw_err("Failed by Config[url]."));
-        }
+impl OutputTarget{
 
-        if !conf.has_key("charset") && !conf["charset"].is_string() {
-            return Err(throw_err("Failed by Config[charset]."));
+    // The key that is missing for this save_type, if any.
+    pub fn check(&self)->Result<(),&'static str>{
+        if self.save_type != SaveType::None && self.table_name.is_empty() {
+            return Err("table_name");
         }
 
-        if !conf.has_key("save_type") && !conf["save_type"].is_string() {
-            return Err(throw_err("Failed by Config[save_type]."));
+        if (self.save_type == SaveType::Redis || self.save_type == SaveType::MySQL) &&
+            self.driver_url.is_empty() {
+            return Err("driver_url");
         }
-
-        let save_type_str = conf["save_type"].to_string().to_lowercase();
-        let save_type = match save_type_str.as_str() {
-            "redis" => SaveType::Redis,
-            "mysql" => SaveType::MySQL,
-            "file" => SaveType::File,
-            _ => SaveType::None
-        };
-
-
-        let driver_url = if  save_type != SaveType::None &&
-            conf.has_key("driver_url") &&
-            conf["driver_url"].is_string() {
-            conf["driver_url"].to_string()
-        }else {
-            String::new()
-        };
-
-        let table_name = if conf.has_key("table_name") &&
-            conf["table_name"].is_string() {
-            conf["table_name"].to_string()
-        }else if save_type != SaveType::None {
-            return Err(throw_err("Failed by Config[table_name]."));
-        }else{
-            String::new()
-        };
-
-        let append_date = if  save_type != SaveType::None &&
-            conf.has_key("append_date") &&
-            conf["append_date"].is_string() {
-            conf["append_date"].to_string()
-        }else {
-            String::new()
-        };
-
+        Ok(())
+    }
+}
 
 
-        let title_tag = if !conf.has_key("title_tag") &&
-            !conf["title_tag"].is_string() {
-            RSS_DEFAULT_TITLE_TAG.to_string()
-        }else{
-            conf["title_tag"].to_string()
-        };
+impl CliConfig{
 
-        let link_tag = if !conf.has_key("link_tag") &&
-            !conf["link_tag"].is_string() {
-            RSS_DEFAULT_LINK_TAG.to_string()
-        }else{
-            conf["link_tag"].to_string()
-        };
+    // `outputs` when given, otherwise the single output of save_type/driver_url/table_name/append_date.
+    pub fn outputs(&self)->Vec<OutputTarget>{
+        if !self.outputs.is_empty() {
+            return self.outputs.clone();
+        }
 
-        let author_tag = if !conf.has_key("author_tag") &&
-            !conf["author_tag"].is_string() {
-            RSS_DEFAULT_AUTHOR_TAG.to_string()
-        }else{
-            conf["author_tag"].to_string()
-        };
+        vec![OutputTarget{
+            save_type: self.save_type.clone(),
+            driver_url: self.driver_url.clone(),
+            table_name: self.table_name.clone(),
+            append_date: self.append_date.clone(),
+            retries: 0,
+            retry_delay: default_retry_delay(),
+            tags: Vec::new(),
+        }]
+    }
 
-        let description_tag = if !conf.has_key("description_tag") &&
-            !conf["description_tag"].is_string() {
-            RSS_DEFAULT_DESC_TAG.to_string()
-        }else{
-            conf["description_tag"].to_string()
-        };
 
+    pub fn from(filename:&str)->Result<Self,std::io::Error>{
+        let doc = Document::load(filename)?;
+        Self::from_document(&doc)
+    }
 
-        let guid_tag = if !conf.has_key("guid_tag") &&
-            !conf["guid_tag"].is_string() {
-            RSS_DEFAULT_GUID_TAG.to_string()
-        }else{
-            conf["guid_tag"].to_string()
-        };
 
-        let publish_tag = if !conf.has_key("publish_tag") &&
-            !conf["publish_tag"].is_string() {
-            RSS_DEFAULT_PUBLISH_TAG.to_string()
-        }else{
-            conf["publish_tag"].to_string()
-        };
+    // Every feed file of a conf.d-style directory with its filename, see `loader::dir_files`.
+    pub fn from_dir(dir:&str)->Result<Vec<DirEntry>,std::io::Error>{
+        let (partials,feeds) = loader::dir_files(dir)?;
+        Ok(feeds.into_iter()
+            .map(|filename|{
+                let conf = Document::load_with(filename.as_str(),partials.as_slice())
+                    .and_then(|d| Self::from_document(&d));
+                (filename,conf)
+            })
+            .collect())
+    }
 
 
-        Ok(Self{
-            url: conf["url"].to_string(),
-            charset: conf["charset"].to_string(),
+    pub fn from_document(doc:&Document)->Result<Self,std::io::Error>{
+        let mut conf:Self = doc.deserialize()?;
+        conf.fetcher = conf.fetcher.to_lowercase();
+        conf.fetch_content = conf.fetch_content.to_lowercase();
 
-            save_type,
-            driver_url,
-            table_name,
-            append_date,
+        if conf.url.trim().is_empty() {
+            return Err(doc.error_at("url","Failed by Config[url]."));
+        }
 
-            title_tag,
-            link_tag,
-            author_tag,
-            description_tag,
-            guid_tag,
-            publish_tag,
-        })
-    }
-}
+        if conf.fetcher != "http" && conf.fetcher != "chrome" {
+            return Err(doc.error_at("fetcher","expected `http` or `chrome`"));
+        }
 
+        if !conf.fetch_content.is_empty() && conf.fetch_content != "http" && conf.fetch_content != "chrome" {
+            return Err(doc.error_at("fetch_content","expected `http` or `chrome`"));
+        }
 
-impl OutputConfig {
-    pub fn from<'a>(filename: &str) -> Result<Self, std::io::Error> {
-        let json_config = std::fs::read_to_string(filename)?;
-        let conf = match json::parse(json_config.as_str()) {
-            Ok(c) => c,
-            Err(e) => {
-                return Err(throw_err(e.to_string().as_str()));
-            }
-        };
+        if !conf.http.proxy.is_empty() && reqwest::Proxy::all(conf.http.proxy.as_str()).is_err() {
+            return Err(doc.error_at("http.proxy","expected an http://, https:// or socks5:// url"));
+        }
 
-        if !conf.is_object() {
-            return Err(throw_err("Failed by Config[Json File]."));
+        if conf.http.retry.attempts == 0 {
+            return Err(doc.error_at("http.retry.attempts","expected at least 1 attempt"));
         }
 
-        if !conf.has_key("chrome_path") && !conf["chrome_path"].is_string()  {
-            return Err(throw_err("Failed by Config[url]."));
+        if conf.http.retry.backoff < 0.0 || conf.http.retry.jitter < 0.0 || conf.http.host_interval < 0.0 {
+            return Err(doc.error_at("http","expected non-negative durations"));
         }
 
-        if !conf.has_key("headless") && !conf["headless"].is_boolean()  {
-            return Err(throw_err("Failed by Config[headless]."));
+        conf.filters.check(doc,"filters.")?;
+        for (tag,rules) in conf.tags.iter() {
+            check_rules(doc,format!("tags.{}",tag).as_str(),rules.as_slice())?;
         }
 
-        if !conf.has_key("sandbox") && !conf["sandbox"].is_boolean()  {
-            return Err(throw_err("Failed by Config[sandbox]."));
+        if conf.classifier.min_confidence < 0.0 || conf.classifier.min_confidence > 1.0 {
+            return Err(doc.error_at("classifier.min_confidence","expected a probability from 0 to 1"));
         }
 
-        if !conf.has_key("idle_browser_timeout") && !conf["idle_browser_timeout"].is_number()  {
-            return Err(throw_err("Failed by Config[idle_browser_timeout]."));
+        if let Some(cluster) = &conf.cluster {
+            if cluster.threshold >= 64 {
+                return Err(doc.error_at("cluster.threshold","expected fewer than 64 bits"));
+            }
         }
 
-        if !conf.has_key("window_size_width") && !conf["window_size_width"].is_number()  {
-            return Err(throw_err("Failed by Config[window_size_width]."));
+        if conf.uid.from == UidFrom::Template {
+            if conf.uid.template.trim().is_empty() {
+                return Err(doc.error_at("uid.template","expected a template like {title}|{publish}"));
+            }
+            let unknown = conf.uid.template.split('{')
+                .skip(1)
+                .filter_map(|part| part.split('}').next())
+                .find(|name| !ITEM_FIELDS.contains(name));
+            if let Some(name) = unknown {
+                let message = format!("unknown field `{}`, expected one of {}",name,ITEM_FIELDS.join(", "));
+                return Err(doc.error_at("uid.template",message.as_str()));
+            }
         }
 
-        if !conf.has_key("window_size_height") && !conf["window_size_height"].is_number()  {
-            return Err(throw_err("Failed by Config[window_size_height]."));
+        if conf.source_type == SourceType::Scrape && conf.scrape.item.trim().is_empty() {
+            return Err(doc.error_at("scrape.item","Failed by Config[scrape.item]."));
         }
 
+        if !conf.outputs.is_empty() {
+            for (i,output) in conf.outputs.iter().enumerate() {
+                if let Err(key) = output.check() {
+                    let path = format!("outputs[{}].{}",i,key);
+                    return Err(doc.error_at(path.as_str(),format!("Failed by Config[{}].",path).as_str()));
+                }
+            }
+        }else if let Err(key) = conf.outputs()[0].check() {
+            return Err(doc.error_at(key,format!("Failed by Config[{}].",key).as_str()));
+        }
 
+        Ok(conf)
+    }
+}
 
 
-        if !conf.has_key("output_type") && !conf["output_type"].is_string() {
-            return Err(throw_err("Failed by Config[output_type]."));
-        }
+impl OutputConfig {
 
-        let output_type_str = conf["output_type"].to_string().to_lowercase();
-        let output_type = match output_type_str.as_str() {
-            "jpeg" => OutputType::JPEG(
-                if !conf.has_key("output_quality") && !conf["output_quality"].is_number(){
-                    100
-                }else {
-                    conf["output_quality"].as_u32().unwrap()
-                }
-            ),
-            "png" => OutputType::PNG,
-            "pdf" => OutputType::PDF,
-            _ => OutputType::None
-        };
+    pub fn from(filename: &str) -> Result<Self, std::io::Error> {
+        let doc = Document::load(filename)?;
+        Self::from_document(&doc)
+    }
 
 
-        let output_path = if !conf.has_key("output_path") &&
-            !conf["output_path"].is_string() {
-            String::from(".")
-        }else{
-            conf["output_path"].to_string()
-        };
+    pub fn from_document(doc:&Document)->Result<Self,std::io::Error>{
+        let conf:OutputConfigFile = doc.deserialize()?;
 
-        let append_date = if !conf.has_key("append_date") &&
-            !conf["append_date"].is_string() {
-            String::new()
-        }else{
-            conf["append_date"].to_string()
+        let output_type = match conf.output_type {
+            OutputFormat::Jpeg => {
+                if conf.output_quality < 1 || conf.output_quality > 100 {
+                    return Err(doc.error_at("output_quality","expected a quality between 1 and 100"));
+                }
+                OutputType::JPEG(conf.output_quality)
+            },
+            OutputFormat::Png => OutputType::PNG,
+            OutputFormat::Pdf => OutputType::PDF,
+            OutputFormat::Mhtml => OutputType::MHTML,
+            OutputFormat::Warc => OutputType::WARC,
+            OutputFormat::None => OutputType::None
         };
 
-
+        let output_path = conf.output_path.clone();
+        let change_detection = conf.change_detection.map(|mut detection|{
+            if detection.state_path.is_empty() {
+                detection.state_path = format!("{}/.state",output_path);
+            }
+            detection
+        });
 
         Ok(Self {
-            chrome_path:conf["chrome_path"].to_string(),
-            headless: conf["headless"].as_bool().unwrap(),
-            sandbox: conf["sandbox"].as_bool().unwrap(),
-            idle_browser_timeout: std::time::Duration::new(conf["idle_browser_timeout"].as_u64().unwrap(),0),
-            window_size_width: conf["window_size_width"].as_u32().unwrap(),
-            window_size_height: conf["window_size_height"].as_u32().unwrap(),
+            chrome_path: conf.chrome_path,
+            headless: conf.headless,
+            sandbox: conf.sandbox,
+            idle_browser_timeout: std::time::Duration::new(conf.idle_browser_timeout,0),
+            window_size_width: conf.window_size_width,
+            window_size_height: conf.window_size_height,
             output_type,
             output_path,
-            append_date
+            append_date: conf.append_date,
+            urls: conf.urls,
+            change_detection,
+            metrics_path: conf.metrics_path,
         })
     }
-}
\ No newline at end of file
+}
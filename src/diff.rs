@@ -0,0 +1,119 @@
+use crate::throw_err;
+use image::{GenericImageView, Rgba, RgbaImage};
+
+
+pub struct DiffResult{
+    pub score: f64,
+    pub changed_pixels: u64,
+    pub image: Vec<u8>,
+}
+
+
+fn distance(a:&Rgba<u8>,b:&Rgba<u8>)->u8{
+    (0..3).map(|i| (a.0[i] as i16 - b.0[i] as i16).unsigned_abs() as u8)
+        .max()
+        .unwrap_or(0)
+}
+
+
+pub fn to_png(data:&[u8])->Result<Vec<u8>,std::io::Error>{
+    let img = image::load_from_memory(data).map_err(|e|throw_err(e.to_string().as_str()))?;
+    let mut png = std::io::Cursor::new(Vec::new());
+    img.write_to(&mut png,image::ImageFormat::Png).map_err(|e|throw_err(e.to_string().as_str()))?;
+    Ok(png.into_inner())
+}
+
+
+// Pixel diff of two captures: a pixel counts as changed when any channel moves by more than
+// `tolerance`, pixels outside the overlapping area always count. The score is the changed ratio.
+pub fn compare(previous:&[u8],current:&[u8],tolerance:u8)->Result<DiffResult,std::io::Error>{
+    let before = image::load_from_memory(previous).map_err(|e|throw_err(e.to_string().as_str()))?;
+    let after = image::load_from_memory(current).map_err(|e|throw_err(e.to_string().as_str()))?;
+
+    let (bw,bh) = before.dimensions();
+    let (aw,ah) = after.dimensions();
+    let width = bw.max(aw);
+    let height = bh.max(ah);
+
+    let before = before.to_rgba8();
+    let after = after.to_rgba8();
+    let mut output = RgbaImage::new(width,height);
+    let mut changed_pixels:u64 = 0;
+
+    for y in 0..height {
+        for x in 0..width {
+            let old = if x < bw && y < bh { Some(before.get_pixel(x,y)) } else { None };
+            let new = if x < aw && y < ah { Some(after.get_pixel(x,y)) } else { None };
+
+            let changed = match (old,new) {
+                (Some(a),Some(b)) => distance(a,b) > tolerance,
+                _ => true
+            };
+
+            // Changed pixels are painted red over a faded copy of the new capture.
+            let pixel = if changed {
+                changed_pixels += 1;
+                Rgba([255,0,0,255])
+            }else{
+                let p = new.or(old).unwrap();
+                let gray = ((p.0[0] as u32 * 30 + p.0[1] as u32 * 59 + p.0[2] as u32 * 11) / 100) as u8;
+                let faded = 255 - (255 - gray) / 3;
+                Rgba([faded,faded,faded,255])
+            };
+            output.put_pixel(x,y,pixel);
+        }
+    }
+
+    let total = (width as u64 * height as u64).max(1);
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgba8(output)
+        .write_to(&mut png,image::ImageFormat::Png)
+        .map_err(|e|throw_err(e.to_string().as_str()))?;
+
+    Ok(DiffResult{
+        score: changed_pixels as f64 / total as f64,
+        changed_pixels,
+        image: png.into_inner(),
+    })
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::png;
+
+    #[test]
+    fn same_capture(){
+        let white = png(4,4,|_,_| [255,255,255]);
+        let ret = compare(white.as_slice(),white.as_slice(),0).unwrap();
+        assert_eq!((ret.changed_pixels,ret.score),(0,0.0));
+        let diff = image::load_from_memory(ret.image.as_slice()).unwrap();
+        assert_eq!(diff.dimensions(),(4,4));
+    }
+
+    #[test]
+    fn different_sizes(){
+        // 3x2 over 2x3: the union is 3x3, every pixel but the 2x2 overlap counts.
+        let before = png(2,3,|_,_| [0,0,0]);
+        let after = png(3,2,|_,_| [0,0,0]);
+        let ret = compare(before.as_slice(),after.as_slice(),0).unwrap();
+        assert_eq!(ret.changed_pixels,5);
+        assert!((ret.score - 5.0 / 9.0).abs() < 1e-9);
+        let diff = image::load_from_memory(ret.image.as_slice()).unwrap().to_rgba8();
+        assert_eq!(diff.dimensions(),(3,3));
+        assert_eq!(diff.get_pixel(2,2).0,[255,0,0,255]);
+        assert_ne!(diff.get_pixel(0,0).0,[255,0,0,255]);
+    }
+
+    #[test]
+    fn pixel_tolerance(){
+        let before = png(2,2,|_,_| [100,100,100]);
+        let after = png(2,2,|x,_| if x == 0 { [110,100,100] } else { [100,100,100] });
+        assert_eq!(compare(before.as_slice(),after.as_slice(),10).unwrap().changed_pixels,0);
+        let ret = compare(before.as_slice(),after.as_slice(),9).unwrap();
+        assert_eq!(ret.changed_pixels,2);
+        assert_eq!(ret.score,0.5);
+        assert!(compare(before.as_slice(),b"not an image",0).is_err());
+    }
+}
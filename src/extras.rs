@@ -0,0 +1,206 @@
+use crate::item::{Enclosure, Item};
+
+
+const MEDIA_NS:&str = "http://search.yahoo.com/mrss/";
+
+
+// What the six mapped tags leave out: categories, enclosures, media thumbnails and the comments page.
+#[derive(Debug,Clone,Default)]
+struct Extras{
+    key: String,
+    comments: String,
+    categories: Vec<String>,
+    enclosures: Vec<Enclosure>,
+    thumbnails: Vec<String>,
+}
+
+
+fn text(node:&roxmltree::Node)->String{
+    node.text().unwrap_or("").trim().to_string()
+}
+
+
+fn push<T:PartialEq>(list:&mut Vec<T>,value:T){
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+
+fn enclosure(url:&str,mime_type:Option<&str>,length:Option<&str>)->Enclosure{
+    Enclosure{
+        url: url.trim().to_string(),
+        mime_type: mime_type.unwrap_or("").to_string(),
+        length: length.and_then(|l| l.trim().parse::<u64>().ok()).unwrap_or(0),
+    }
+}
+
+
+// RSS `<item>` and Atom `<entry>` elements.
+fn parse_item(item:roxmltree::Node)->Extras{
+    let mut extras = Extras::default();
+    let mut guid = String::new();
+    let mut link = String::new();
+
+    for child in item.children().filter(|n| n.is_element()) {
+        let name = child.tag_name().name();
+        if child.tag_name().namespace() == Some(MEDIA_NS) {
+            continue;
+        }
+        match name {
+            "guid" | "id" => guid = text(&child),
+            "link" => match child.attribute("rel") {
+                Some("enclosure") => if let Some(href) = child.attribute("href") {
+                    push(&mut extras.enclosures,enclosure(href,child.attribute("type"),child.attribute("length")));
+                },
+                Some("replies") => extras.comments = child.attribute("href").unwrap_or("").to_string(),
+                Some("alternate") | None if link.is_empty() => {
+                    link = child.attribute("href").map(|h| h.to_string()).unwrap_or_else(|| text(&child));
+                }
+                _ => {}
+            },
+            "category" => {
+                let category = child.attribute("term").map(|t| t.trim().to_string()).unwrap_or_else(|| text(&child));
+                if !category.is_empty() {
+                    push(&mut extras.categories,category);
+                }
+            }
+            "enclosure" => if let Some(url) = child.attribute("url") {
+                push(&mut extras.enclosures,enclosure(url,child.attribute("type"),child.attribute("length")));
+            },
+            "comments" => extras.comments = text(&child),
+            _ => {}
+        }
+    }
+
+    // media:content and media:thumbnail, also inside media:group.
+    for media in item.descendants().filter(|n| n.is_element() && n.tag_name().namespace() == Some(MEDIA_NS)) {
+        match media.tag_name().name() {
+            "content" => if let Some(url) = media.attribute("url") {
+                let mime_type = media.attribute("type").or_else(|| media.attribute("medium"));
+                push(&mut extras.enclosures,enclosure(url,mime_type,media.attribute("fileSize")));
+            },
+            "thumbnail" => if let Some(url) = media.attribute("url") {
+                push(&mut extras.thumbnails,url.trim().to_string());
+            },
+            _ => {}
+        }
+    }
+
+    extras.key = if !guid.is_empty() { guid } else { link };
+    extras
+}
+
+
+fn parse(xml:&str)->Vec<Extras>{
+    let options = roxmltree::ParsingOptions{ allow_dtd: true, ..Default::default() };
+    let document = match roxmltree::Document::parse_with_options(xml,options) {
+        Ok(d) => d,
+        Err(e) => {
+            tracing::warn!("Failed by parse extras, no enclosures, categories or thumbnails: {}",e);
+            return Vec::new();
+        }
+    };
+
+    document.descendants()
+        .filter(|n| n.is_element() && (n.tag_name().name() == "item" || n.tag_name().name() == "entry"))
+        .map(parse_item)
+        .collect()
+}
+
+
+// Items are matched in document order, by guid or link when the parser skipped some.
+pub fn apply(items:&mut [Item],xml:&str){
+    let extras = parse(xml);
+    let same_order = extras.len() == items.len();
+
+    for (i,item) in items.iter_mut().enumerate() {
+        let found = if same_order {
+            extras.get(i)
+        }else{
+            extras.iter().find(|e| !e.key.is_empty() && (e.key == item.guid || e.key == item.link))
+        };
+
+        if let Some(e) = found {
+            item.comments = e.comments.clone();
+            item.categories = e.categories.clone();
+            item.enclosures = e.enclosures.clone();
+            item.thumbnails = e.thumbnails.clone();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn item(guid:&str,link:&str)->Item{
+        Item{ guid: guid.to_string(), link: link.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn rss_enclosures_and_categories(){
+        let xml = r#"<rss xmlns:media="http://search.yahoo.com/mrss/"><channel><item>
+            <guid>a</guid>
+            <category>Rust</category><category> Rust </category><category/>
+            <enclosure url=" https://example.com/a.mp3 " type="audio/mpeg" length="123"/>
+            <enclosure url="https://example.com/a.mp3" type="audio/mpeg" length="123"/>
+            <enclosure type="audio/mpeg"/>
+            <comments>https://example.com/a#comments</comments>
+            <media:group>
+                <media:content url="https://example.com/a.mp4" medium="video" fileSize="x"/>
+                <media:thumbnail url="https://example.com/a.jpg"/>
+            </media:group>
+            <media:thumbnail url="https://example.com/a.jpg"/>
+        </item></channel></rss>"#;
+        let mut items = vec![item("a","")];
+        apply(items.as_mut_slice(),xml);
+
+        assert_eq!(items[0].categories,vec!["Rust"]);
+        assert_eq!(items[0].enclosures,vec![
+            Enclosure{ url: String::from("https://example.com/a.mp3"), mime_type: String::from("audio/mpeg"), length: 123 },
+            Enclosure{ url: String::from("https://example.com/a.mp4"), mime_type: String::from("video"), length: 0 },
+        ]);
+        assert_eq!(items[0].thumbnails,vec!["https://example.com/a.jpg"]);
+        assert_eq!(items[0].comments,"https://example.com/a#comments");
+    }
+
+    #[test]
+    fn atom_links(){
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom"><entry>
+            <id>tag:example.com,2020:a</id>
+            <link href="https://example.com/a"/>
+            <link rel="enclosure" href="https://example.com/a.pdf" type="application/pdf" length="7"/>
+            <link rel="replies" href="https://example.com/a/comments"/>
+            <category term="news"/>
+        </entry></feed>"#;
+        let extras = parse(xml);
+        assert_eq!(extras.len(),1);
+        assert_eq!(extras[0].key,"tag:example.com,2020:a");
+        assert_eq!(extras[0].enclosures[0].url,"https://example.com/a.pdf");
+        assert_eq!(extras[0].enclosures[0].length,7);
+        assert_eq!(extras[0].comments,"https://example.com/a/comments");
+        assert_eq!(extras[0].categories,vec!["news"]);
+    }
+
+    #[test]
+    fn matched_by_guid_or_link(){
+        // The parser kept two of three items, they are found by guid and by link.
+        let xml = r#"<rss><channel>
+            <item><guid>a</guid><category>first</category></item>
+            <item><link>https://example.com/b</link><category>second</category></item>
+            <item><guid>c</guid><category>third</category></item>
+        </channel></rss>"#;
+        let mut items = vec![item("c",""),item("","https://example.com/b")];
+        apply(items.as_mut_slice(),xml);
+        assert_eq!(items[0].categories,vec!["third"]);
+        assert_eq!(items[1].categories,vec!["second"]);
+
+        let mut unknown = vec![item("x","")];
+        apply(unknown.as_mut_slice(),"<rss><item><guid>a</guid></item><item><guid>b</guid></item></rss>");
+        assert!(unknown[0].categories.is_empty());
+        apply(unknown.as_mut_slice(),"<rss><item>");
+        assert!(unknown[0].enclosures.is_empty());
+    }
+}
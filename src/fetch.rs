@@ -0,0 +1,479 @@
+use crate::browser::ChromeFetcher;
+use crate::config::{HttpConfig, RetryPolicy};
+use crate::metrics;
+use crate::throw_err;
+use base64::Engine;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+
+// Feeds labelled gb2312/gbk often carry GB18030-only characters, decode them with the superset.
+fn superset(encoding:&'static encoding_rs::Encoding)->&'static encoding_rs::Encoding{
+    if encoding == encoding_rs::GBK {
+        return encoding_rs::GB18030;
+    }
+    encoding
+}
+
+
+// `charset=` of a Content-Type header or meta tag, `encoding=` of an XML declaration.
+fn label_after(text:&str,key:&str)->Option<&'static encoding_rs::Encoding>{
+    let start = text.find(key)? + key.len();
+    let label:String = text[start..].trim_start_matches(['"','\'',' '])
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_' || *c == ':' || *c == '.')
+        .collect();
+    let encoding = encoding_rs::Encoding::for_label(label.as_bytes())?;
+
+    // Without a BOM a declared UTF-16 document is already readable as ASCII, so the label is wrong.
+    if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+        return None;
+    }
+    Some(encoding)
+}
+
+
+fn declared(bytes:&[u8])->Option<&'static encoding_rs::Encoding>{
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_lowercase();
+    if head.trim_start().starts_with("<?xml") {
+        let decl = &head[..head.find("?>").unwrap_or(head.len())];
+        if let Some(encoding) = label_after(decl,"encoding=") {
+            return Some(encoding);
+        }
+    }
+    label_after(head.as_str(),"charset=")
+}
+
+
+// BOM, then the Content-Type header, then the XML declaration or meta tag; a label that does not
+// decode cleanly is skipped and the statistical guess of chardetng is the last resort.
+pub fn detect(bytes:&[u8],content_type:&str)->&'static encoding_rs::Encoding{
+    if let Some((encoding,_)) = encoding_rs::Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    let candidates = [
+        label_after(content_type.to_lowercase().as_str(),"charset="),
+        declared(bytes),
+    ];
+    for encoding in candidates.iter().flatten() {
+        let encoding = superset(encoding);
+        if encoding.decode_without_bom_handling_and_without_replacement(bytes).is_some() {
+            return encoding;
+        }
+    }
+
+    let mut detector = chardetng::EncodingDetector::new(chardetng::Iso2022JpDetection::Deny);
+    detector.feed(bytes,true);
+    superset(detector.guess(None,chardetng::Utf8Detection::Allow))
+}
+
+
+// `charset` is a label or `auto`, returns the text with the name of the encoding used.
+pub fn transcode(bytes:&[u8],charset:&str,content_type:&str)->(String,&'static str){
+    let charset = charset.trim();
+    let encoding = if charset.is_empty() || charset.eq_ignore_ascii_case("auto") {
+        detect(bytes,content_type)
+    }else{
+        superset(encoding_rs::Encoding::for_label(charset.as_bytes()).unwrap_or(encoding_rs::UTF_8))
+    };
+
+    let (text,used,had_errors) = encoding.decode(bytes);
+    if had_errors {
+        tracing::warn!(charset = used.name(),"Failed by charset, the text has undecodable bytes ( try `charset: auto` ).");
+    }
+    (text.into_owned(),used.name())
+}
+
+
+pub fn decode(bytes:&[u8],charset:&str)->String{
+    transcode(bytes,charset,"").0
+}
+
+
+// Next free slot of every host, shared by all feeds and article fetches of the process.
+static HOSTS:OnceLock<Mutex<HashMap<String,Instant>>> = OnceLock::new();
+
+
+// Wait until `interval` has passed since the previous request to the host of `url`.
+pub fn polite(url:&str,interval:f64){
+    if interval <= 0.0 {
+        return;
+    }
+    let host = match url::Url::parse(url) {
+        Ok(u) => u.host_str().unwrap_or("").to_string(),
+        Err(_e) => return
+    };
+
+    let wait = {
+        let mut hosts = HOSTS.get_or_init(|| Mutex::new(HashMap::new())).lock().unwrap();
+        let now = Instant::now();
+        let slot = hosts.get(host.as_str()).cloned().filter(|t| *t > now).unwrap_or(now);
+        hosts.insert(host,slot + Duration::from_secs_f64(interval));
+        slot - now
+    };
+
+    if wait > Duration::from_millis(0) {
+        std::thread::sleep(wait);
+    }
+}
+
+
+// Delay before the retry after `attempt` failed attempts, `Retry-After` of the response wins up to `max_backoff`.
+fn backoff(policy:&RetryPolicy,attempt:u32,retry_after:Option<Duration>)->Duration{
+    if let Some(after) = retry_after {
+        return after.min(Duration::from_secs_f64(policy.max_backoff));
+    }
+    let base = (policy.backoff * 2f64.powi(attempt as i32 - 1)).min(policy.max_backoff);
+    Duration::from_secs_f64(base * (1.0 + policy.jitter * rand::random::<f64>()))
+}
+
+
+// `Retry-After` is either seconds or an HTTP date.
+fn retry_after(response:&reqwest::blocking::Response)->Option<Duration>{
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim().to_string();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.as_str()).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+
+// Headers sent with every request of a feed, shared by the http client and Chrome.
+pub fn request_headers(http:&HttpConfig)->Vec<(String,String)>{
+    let mut headers:Vec<(String,String)> = http.headers.iter()
+        .map(|(k,v)| (k.clone(),v.clone()))
+        .collect();
+
+    if !http.referer.is_empty() {
+        headers.push((String::from("Referer"),http.referer.clone()));
+    }
+
+    if !http.cookies.is_empty() {
+        let cookie = http.cookies.iter()
+            .map(|(k,v)| format!("{}={}",k,v))
+            .collect::<Vec<String>>()
+            .join("; ");
+        headers.push((String::from("Cookie"),cookie));
+    }
+
+    if let Some(auth) = &http.basic_auth {
+        let token = base64::engine::general_purpose::STANDARD
+            .encode(format!("{}:{}",auth.username,auth.password));
+        headers.push((String::from("Authorization"),format!("Basic {}",token)));
+    }
+    headers
+}
+
+
+pub struct Fetcher{
+    client: reqwest::blocking::Client,
+    status: std::cell::Cell<u16>,
+    retry: RetryPolicy,
+    host_interval: f64,
+}
+
+impl Fetcher{
+
+    pub fn new()->Result<Self,std::io::Error>{
+        Self::from(&HttpConfig::default())
+    }
+
+
+    pub fn from(http:&HttpConfig)->Result<Self,std::io::Error>{
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (key,value) in request_headers(http) {
+            let name = match reqwest::header::HeaderName::from_bytes(key.as_bytes()) {
+                Ok(n) => n,
+                Err(_e) => return Err(throw_err(format!("Failed by Config[http.headers.{}].",key).as_str()))
+            };
+            let value = match reqwest::header::HeaderValue::from_str(value.as_str()) {
+                Ok(v) => v,
+                Err(_e) => return Err(throw_err(format!("Failed by Config[http.headers.{}].",key).as_str()))
+            };
+            headers.insert(name,value);
+        }
+
+        let redirect = if http.max_redirects > 0 {
+            reqwest::redirect::Policy::limited(http.max_redirects)
+        }else{
+            reqwest::redirect::Policy::none()
+        };
+
+        let mut builder = reqwest::blocking::Client::builder()
+            .default_headers(headers)
+            .redirect(redirect);
+
+        if !http.user_agent.is_empty() {
+            builder = builder.user_agent(http.user_agent.as_str());
+        }
+
+        if !http.proxy.is_empty() {
+            builder = match reqwest::Proxy::all(http.proxy.as_str()) {
+                Ok(p) => builder.proxy(p),
+                Err(e) => return Err(throw_err(e.to_string().as_str()))
+            };
+        }
+
+        if http.connect_timeout > 0 {
+            builder = builder.connect_timeout(std::time::Duration::from_secs(http.connect_timeout));
+        }
+
+        if http.read_timeout > 0 {
+            builder = builder.timeout(std::time::Duration::from_secs(http.read_timeout));
+        }
+
+        let client = match builder.build() {
+            Ok(c) => c,
+            Err(e) => return Err(throw_err(e.to_string().as_str()))
+        };
+        Ok(Self{
+            client,
+            status: std::cell::Cell::new(0),
+            retry: http.retry.clone(),
+            host_interval: http.host_interval,
+        })
+    }
+
+
+    pub fn get_bytes(&self,url:&str)->Result<Vec<u8>,std::io::Error>{
+        Ok(self.get_response(url)?.0)
+    }
+
+
+    // Body and Content-Type, connection errors, timeouts and the statuses of the retry policy are retried.
+    pub fn get_response(&self,url:&str)->Result<(Vec<u8>,String),std::io::Error>{
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            polite(url,self.host_interval);
+
+            let (err,after) = match self.client.get(url).send() {
+                Ok(mut response) => {
+                    let status = response.status();
+                    self.status.set(status.as_u16());
+                    metrics::http_response(url,status.as_u16());
+                    if status.is_success() {
+                        let content_type = response.headers()
+                            .get(reqwest::header::CONTENT_TYPE)
+                            .and_then(|v| v.to_str().ok())
+                            .unwrap_or("")
+                            .to_string();
+                        let mut body = Vec::new();
+                        response.read_to_end(&mut body)?;
+                        return Ok((body,content_type));
+                    }
+
+                    let err = throw_err(format!("Failed by request {} [{}].",url,status).as_str());
+                    if !self.retry.statuses.contains(&status.as_u16()) {
+                        return Err(err);
+                    }
+                    (err,retry_after(&response))
+                }
+                Err(e) => {
+                    self.status.set(0);
+                    metrics::http_response(url,0);
+                    let err = std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string());
+                    if !e.is_connect() && !e.is_timeout() && !e.is_request() {
+                        return Err(err);
+                    }
+                    (err,None)
+                }
+            };
+
+            if attempt >= self.retry.attempts {
+                return Err(err);
+            }
+            let delay = backoff(&self.retry,attempt,after);
+            tracing::warn!(url,delay = delay.as_secs_f64(),attempt,attempts = self.retry.attempts,"Retry: {}",err);
+            std::thread::sleep(delay);
+        }
+    }
+
+
+    // Where the redirects of the url end, the body is not read.
+    pub fn resolve(&self,url:&str)->Result<String,std::io::Error>{
+        polite(url,self.host_interval);
+        match self.client.get(url).send() {
+            Ok(response) => {
+                metrics::http_response(url,response.status().as_u16());
+                Ok(response.url().to_string())
+            }
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
+        }
+    }
+
+
+    // HTTP status of the last response, 0 when no response came back.
+    pub fn status(&self)->u16{
+        self.status.get()
+    }
+
+
+    pub fn get_text(&self,url:&str,charset:&str)->Result<String,std::io::Error>{
+        Ok(self.get_decoded(url,charset)?.0)
+    }
+
+
+    pub fn get_decoded(&self,url:&str,charset:&str)->Result<(String,&'static str),std::io::Error>{
+        let (body,content_type) = self.get_response(url)?;
+        Ok(transcode(body.as_slice(),charset,content_type.as_str()))
+    }
+}
+
+
+// Pages requested either by plain http or by headless Chrome, both clients are created on first use.
+pub struct Pages{
+    charset: String,
+    detected: String,
+    chrome_path: String,
+    conf: HttpConfig,
+    http: Option<Fetcher>,
+    chrome: Option<ChromeFetcher>,
+}
+
+impl Pages{
+
+    pub fn new(charset:&str,chrome_path:&str,conf:&HttpConfig)->Self{
+        Self{
+            charset: charset.to_string(),
+            detected: String::new(),
+            chrome_path: chrome_path.to_string(),
+            conf: conf.clone(),
+            http: None,
+            chrome: None,
+        }
+    }
+
+
+    fn chrome(&mut self)->Result<&ChromeFetcher,std::io::Error>{
+        if self.chrome.is_none() {
+            let mut chrome = ChromeFetcher::launch(
+                self.chrome_path.as_str(),
+                true,
+                std::time::Duration::from_secs(30),
+                self.conf.proxy.as_str()
+            )?;
+            chrome.set_headers(self.conf.user_agent.as_str(),request_headers(&self.conf).as_slice())?;
+            // Chrome has no separate connect timeout, the navigation gets the whole budget.
+            let timeout = if self.conf.read_timeout > 0 { self.conf.read_timeout } else { self.conf.connect_timeout };
+            chrome.set_limits(
+                if timeout > 0 { Some(std::time::Duration::from_secs(timeout)) } else { None },
+                self.conf.max_redirects
+            )?;
+            self.chrome = Some(chrome);
+        }
+        Ok(self.chrome.as_ref().unwrap())
+    }
+
+
+    fn http(&mut self)->Result<&Fetcher,std::io::Error>{
+        if self.http.is_none() {
+            self.http = Some(Fetcher::from(&self.conf)?);
+        }
+        Ok(self.http.as_ref().unwrap())
+    }
+
+
+    // Charset of the last page, empty when Chrome decoded it.
+    pub fn charset(&self)->&str{
+        self.detected.as_str()
+    }
+
+
+    pub fn status(&self)->u16{
+        self.http.as_ref().map(|h| h.status()).unwrap_or(0)
+    }
+
+
+    fn get_text(&mut self,url:&str)->Result<String,std::io::Error>{
+        let charset = self.charset.clone();
+        let (text,detected) = self.http()?.get_decoded(url,charset.as_str())?;
+        self.detected = detected.to_string();
+        Ok(text)
+    }
+
+
+    pub fn get_html(&mut self,url:&str,fetcher:&str)->Result<String,std::io::Error>{
+        match fetcher {
+            "chrome" => {
+                polite(url,self.conf.host_interval);
+                self.detected = String::new();
+                self.chrome()?.get_html(url)
+            }
+            _ => self.get_text(url)
+        }
+    }
+
+
+    pub fn resolve(&mut self,url:&str)->Result<String,std::io::Error>{
+        self.http()?.resolve(url)
+    }
+
+
+    pub fn get_xml(&mut self,url:&str,fetcher:&str)->Result<String,std::io::Error>{
+        match fetcher {
+            "chrome" => {
+                polite(url,self.conf.host_interval);
+                self.detected = String::new();
+                self.chrome()?.get_xml(url)
+            }
+            _ => self.get_text(url)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn gbk(text:&str)->Vec<u8>{
+        encoding_rs::GBK.encode(text).0.into_owned()
+    }
+
+    #[test]
+    fn bom_wins(){
+        let mut bytes = vec![0xEF,0xBB,0xBF];
+        bytes.extend_from_slice(b"<?xml version=\"1.0\" encoding=\"gbk\"?><rss/>");
+        assert_eq!(detect(bytes.as_slice(),"text/xml; charset=gbk"),encoding_rs::UTF_8);
+        assert_eq!(detect(&[0xFF,0xFE,b'<',0],"text/xml; charset=utf-8"),encoding_rs::UTF_16LE);
+    }
+
+    #[test]
+    fn content_type_over_declaration(){
+        // Both labels decode the bytes, the header is asked first.
+        let mut bytes = b"<?xml version='1.0' encoding='iso-8859-1'?><title>".to_vec();
+        bytes.extend(gbk("中文"));
+        assert_eq!(detect(bytes.as_slice(),"application/rss+xml; charset=\"GBK\""),encoding_rs::GB18030);
+        assert_eq!(detect(bytes.as_slice(),"application/rss+xml"),encoding_rs::WINDOWS_1252);
+    }
+
+    #[test]
+    fn declared_labels(){
+        assert_eq!(declared(b"<?xml version=\"1.0\" encoding=\"Big5\"?>"),Some(encoding_rs::BIG5));
+        assert_eq!(declared(b"<html><meta charset=\"shift_jis\">"),Some(encoding_rs::SHIFT_JIS));
+        assert_eq!(declared(b"<?xml version=\"1.0\" encoding=\"utf-16\"?>"),None);
+        assert_eq!(declared(b"<rss>"),None);
+    }
+
+    #[test]
+    fn gb2312_decodes_as_gb18030(){
+        assert_eq!(superset(encoding_rs::Encoding::for_label(b"gb2312").unwrap()),encoding_rs::GB18030);
+        let bytes = encoding_rs::GB18030.encode("价格 𠀀").0.into_owned();
+        assert_eq!(transcode(bytes.as_slice(),"gb2312",""),(String::from("价格 𠀀"),"gb18030"));
+        assert_eq!(transcode(bytes.as_slice(),"auto","text/html; charset=gb2312"),(String::from("价格 𠀀"),"gb18030"));
+    }
+
+    #[test]
+    fn wrong_label_falls_through(){
+        let bytes = gbk("<p>今天的新闻：国内经济继续保持稳定增长，各地政府发布了新的政策措施。</p>");
+        let (text,used) = transcode(bytes.as_slice(),"auto","text/html; charset=utf-8");
+        assert_eq!(used,"gb18030");
+        assert!(text.contains("国内经济"));
+        assert_eq!(transcode(b"plain","","").1,"UTF-8");
+    }
+}
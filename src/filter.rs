@@ -0,0 +1,205 @@
+use crate::config::{FilterConfig, FilterRule};
+use crate::item::Item;
+use crate::throw_err;
+
+
+// The description is matched as text, a keyword can not hit markup or an attribute.
+fn field<'a>(item:&'a Item,name:&str)->&'a str{
+    match name {
+        "title" => item.title.as_str(),
+        "description" => item.description_text.as_str(),
+        "author" => item.author.as_str(),
+        "link" => item.link.as_str(),
+        _ => ""
+    }
+}
+
+
+#[derive(Debug,Clone)]
+pub(crate) struct Rule{
+    keywords: Vec<String>,
+    regex: Option<regex::Regex>,
+    author: String,
+    domain: String,
+    fields: Vec<String>,
+}
+
+impl Rule{
+    pub(crate) fn from(rule:&FilterRule)->Result<Self,String>{
+        let regex = if !rule.regex.is_empty() {
+            match regex::RegexBuilder::new(rule.regex.as_str()).case_insensitive(true).build() {
+                Ok(r) => Some(r),
+                Err(e) => return Err(e.to_string())
+            }
+        }else{
+            None
+        };
+
+        Ok(Self{
+            keywords: rule.keywords.iter().map(|k| k.to_lowercase()).collect(),
+            regex,
+            author: rule.author.to_lowercase(),
+            domain: rule.domain.to_lowercase().trim_start_matches('.').to_string(),
+            fields: rule.fields.clone(),
+        })
+    }
+
+
+    // Every condition that is set has to match, one keyword is enough.
+    pub(crate) fn check(&self,item:&Item)->bool{
+        if !self.keywords.is_empty() {
+            let found = self.fields.iter().any(|name|{
+                let text = field(item,name.as_str()).to_lowercase();
+                self.keywords.iter().any(|k| text.contains(k.as_str()))
+            });
+            if !found {
+                return false;
+            }
+        }
+
+        if let Some(regex) = &self.regex {
+            if !self.fields.iter().any(|name| regex.is_match(field(item,name.as_str()))) {
+                return false;
+            }
+        }
+
+        if !self.author.is_empty() && !item.author.to_lowercase().contains(self.author.as_str()) {
+            return false;
+        }
+
+        if !self.domain.is_empty() {
+            let host = url::Url::parse(item.link.as_str())
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.to_lowercase()))
+                .unwrap_or_default();
+            if host != self.domain && !host.ends_with(format!(".{}",self.domain).as_str()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+
+// Items are kept when they match one `include` rule ( or there are none ) and no `exclude` rule.
+#[derive(Debug,Clone,Default)]
+pub struct Filter{
+    include: Vec<Rule>,
+    exclude: Vec<Rule>,
+}
+
+impl Filter{
+
+    // Err carries the key path of the broken rule, e.g. `include[0].regex`.
+    pub fn from(conf:&FilterConfig)->Result<Self,(String,String)>{
+        let compile = |rules:&[FilterRule],name:&str|{
+            rules.iter()
+                .enumerate()
+                .map(|(i,r)| Rule::from(r).map_err(|e| (format!("{}[{}].regex",name,i),e)))
+                .collect::<Result<Vec<Rule>,(String,String)>>()
+        };
+
+        Ok(Self{
+            include: compile(conf.include.as_slice(),"include")?,
+            exclude: compile(conf.exclude.as_slice(),"exclude")?,
+        })
+    }
+
+
+    pub fn load(filename:&str)->Result<Self,std::io::Error>{
+        let conf = FilterConfig::from(filename)?;
+        Self::from(&conf).map_err(|(path,e)| throw_err(format!("{}: {}: {}",filename,path,e).as_str()))
+    }
+
+
+    pub fn check(&self,item:&Item)->bool{
+        if !self.include.is_empty() && !self.include.iter().any(|r| r.check(item)) {
+            return false;
+        }
+        !self.exclude.iter().any(|r| r.check(item))
+    }
+
+
+    // Kept items and the number of dropped ones.
+    pub fn apply(&self,items:Vec<Item>)->(Vec<Item>,usize){
+        let total = items.len();
+        let kept:Vec<Item> = items.into_iter().filter(|item| self.check(item)).collect();
+        let dropped = total - kept.len();
+        (kept,dropped)
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::item;
+
+    fn rule(json:&str)->FilterRule{
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn text(title:&str,text:&str)->Item{
+        Item{ description_text: text.to_string(), ..item(title,"") }
+    }
+
+    #[test]
+    fn keywords_ignore_case(){
+        let rule = Rule::from(&rule(r#"{"keywords":["RUST","Ärger"]}"#)).unwrap();
+        assert!(rule.check(&text("Rust 1.80","")));
+        assert!(rule.check(&text("","kein ärger")));
+        assert!(!rule.check(&text("Go 1.22","")));
+    }
+
+    #[test]
+    fn description_is_matched_as_text(){
+        let rule = Rule::from(&rule(r#"{"keywords":["sponsor"]}"#)).unwrap();
+        let markup = Item{ description_text: String::from("A post"), ..item("",r#"<p class="sponsor">A post</p>"#) };
+        assert!(!rule.check(&markup));
+        assert!(rule.check(&text("","Sponsored post")));
+    }
+
+    #[test]
+    fn fields_default_and_set(){
+        assert_eq!(rule("{}").fields,vec!["title","description"]);
+        let author = Item{ author: String::from("Rust Team"), ..item("","") };
+        assert!(!Rule::from(&rule(r#"{"keywords":["rust"]}"#)).unwrap().check(&author));
+        assert!(Rule::from(&rule(r#"{"keywords":["rust"],"fields":["author"]}"#)).unwrap().check(&author));
+
+        let regex = Rule::from(&rule(r#"{"regex":"^(ad|promo)\\b","fields":["title"]}"#)).unwrap();
+        assert!(regex.check(&text("AD: buy now","")));
+        assert!(!regex.check(&text("Read","ad: buy now")));
+        assert!(Rule::from(&rule(r#"{"regex":"("}"#)).is_err());
+    }
+
+    #[test]
+    fn domain_and_author(){
+        let rule = Rule::from(&rule(r#"{"domain":".Example.com","author":"ann"}"#)).unwrap();
+        let on = |link:&str,author:&str| Item{ link: link.to_string(), author: author.to_string(), ..item("","") };
+        assert!(rule.check(&on("https://example.com/a","Ann")));
+        assert!(rule.check(&on("https://news.EXAMPLE.com/a","Joanna")));
+        assert!(!rule.check(&on("https://badexample.com/a","Ann")));
+        assert!(!rule.check(&on("https://example.com/a","Bob")));
+        assert!(!rule.check(&on("not a url","Ann")));
+    }
+
+    #[test]
+    fn include_and_exclude(){
+        let conf:FilterConfig = serde_json::from_str(r#"{
+            "include":[{"keywords":["rust"]},{"keywords":["go"]}],
+            "exclude":[{"keywords":["job"]}]
+        }"#).unwrap();
+        let filter = Filter::from(&conf).unwrap();
+        let items = vec![text("Rust news",""),text("Go news",""),text("Rust job",""),text("Weather","")];
+        let (kept,dropped) = filter.apply(items);
+        assert_eq!(kept.iter().map(|i| i.title.as_str()).collect::<Vec<&str>>(),vec!["Rust news","Go news"]);
+        assert_eq!(dropped,2);
+
+        let exclude_only:FilterConfig = serde_json::from_str(r#"{"exclude":[{"keywords":["job"]}]}"#).unwrap();
+        assert!(Filter::from(&exclude_only).unwrap().check(&text("Weather","")));
+        assert!(Filter::default().check(&text("Anything","")));
+
+        let broken:FilterConfig = serde_json::from_str(r#"{"exclude":[{},{"regex":"["}]}"#).unwrap();
+        assert_eq!(Filter::from(&broken).unwrap_err().0,"exclude[1].regex");
+    }
+}
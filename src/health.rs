@@ -0,0 +1,149 @@
+use crate::config::HealthConfig;
+use crate::throw_err;
+use serde::{Deserialize, Serialize};
+
+
+// Run history of one feed, times are unix timestamps.
+#[derive(Debug,Clone,Default,Serialize,Deserialize)]
+#[serde(default)]
+pub struct FeedHealth{
+    pub config: String,
+    pub url: String,
+    pub last_run: i64,
+    pub last_success: i64,
+    pub last_error: String,
+    pub consecutive_failures: u32,
+    pub http_status: u16,
+    pub items_parsed: usize,
+    pub items_filtered: usize,
+    pub items_suppressed: usize,
+    pub new_items: usize,
+    pub duration_ms: u64,
+    pub charset: String,
+    pub next_run: i64,
+    pub disabled: bool,
+}
+
+
+impl FeedHealth{
+
+    pub fn due(&self,now:i64)->bool{
+        !self.disabled && self.next_run <= now
+    }
+
+
+    pub fn state(&self)->&'static str{
+        if self.disabled {
+            "disabled"
+        }else if self.consecutive_failures > 0 {
+            "failing"
+        }else if self.last_run > 0 {
+            "ok"
+        }else{
+            "new"
+        }
+    }
+
+
+    pub fn success(&mut self,now:i64){
+        self.last_run = now;
+        self.last_success = now;
+        self.last_error = String::new();
+        self.consecutive_failures = 0;
+        self.next_run = 0;
+    }
+
+
+    pub fn failure(&mut self,now:i64,error:&str,conf:&HealthConfig){
+        self.last_run = now;
+        self.last_error = error.to_string();
+        self.consecutive_failures += 1;
+
+        let shift = (self.consecutive_failures - 1).min(31);
+        let backoff = conf.backoff.saturating_mul(1u64 << shift).min(conf.max_backoff);
+        self.next_run = now + backoff as i64;
+
+        if conf.max_failures > 0 && self.consecutive_failures >= conf.max_failures {
+            self.disabled = true;
+        }
+    }
+}
+
+
+// One JSON file per feed, so feeds run by separate processes never write the same file.
+pub struct HealthStore{
+    dir: std::path::PathBuf,
+}
+
+impl HealthStore{
+
+    pub fn new(state_dir:&str)->Self{
+        Self{
+            dir: std::path::Path::new(state_dir).join("feeds"),
+        }
+    }
+
+
+    fn path(&self,config:&str)->std::path::PathBuf{
+        self.dir.join(format!("{:x}.json",md5::compute(config.as_bytes())))
+    }
+
+
+    pub fn load(&self,config:&str)->FeedHealth{
+        let health = std::fs::read_to_string(self.path(config))
+            .ok()
+            .and_then(|data| serde_json::from_str::<FeedHealth>(data.as_str()).ok());
+
+        match health {
+            Some(h) => h,
+            None => FeedHealth{
+                config: config.to_string(),
+                ..Default::default()
+            }
+        }
+    }
+
+
+    pub fn save(&self,health:&FeedHealth)->Result<(),std::io::Error>{
+        std::fs::create_dir_all(&self.dir)?;
+        let data = match serde_json::to_string_pretty(health) {
+            Ok(d) => d,
+            Err(e) => return Err(throw_err(e.to_string().as_str()))
+        };
+
+        // Written aside and renamed, a crashed run never leaves half a file.
+        let path = self.path(health.config.as_str());
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp,data.as_bytes())?;
+        std::fs::rename(&tmp,&path)
+    }
+
+
+    pub fn reset(&self,config:&str)->Result<(),std::io::Error>{
+        match std::fs::remove_file(self.path(config)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(())
+        }
+    }
+
+
+    pub fn all(&self)->Result<Vec<FeedHealth>,std::io::Error>{
+        let mut list = Vec::new();
+        if !self.dir.is_dir() {
+            return Ok(list);
+        }
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().map(|e| e != "json").unwrap_or(true) {
+                continue;
+            }
+            let data = std::fs::read_to_string(&path)?;
+            if let Ok(health) = serde_json::from_str::<FeedHealth>(data.as_str()) {
+                list.push(health);
+            }
+        }
+        list.sort_by(|a,b| a.config.cmp(&b.config));
+        Ok(list)
+    }
+}
@@ -0,0 +1,263 @@
+use crate::config::{UidConfig, UidFrom, UidHash};
+
+
+fn hex(bytes:&[u8])->String{
+    bytes.iter().map(|b| format!("{:02x}",b)).collect()
+}
+
+
+#[derive(Debug,Clone,Default,PartialEq)]
+pub struct Enclosure{
+    pub url: String,
+    pub mime_type: String,
+    pub length: u64,
+}
+
+
+#[derive(Debug,Clone,Default)]
+pub struct Item{
+    // Set by `make_uid` with the uid config of the feed, empty before.
+    pub uid: String,
+    pub title: String,
+    pub link: String,
+    pub author: String,
+    pub description: String,
+    pub description_html: String,
+    pub description_text: String,
+    pub guid: String,
+    pub publish: String,
+    pub content: String,
+    pub comments: String,
+    pub categories: Vec<String>,
+    pub enclosures: Vec<Enclosure>,
+    pub thumbnails: Vec<String>,
+    pub tags: Vec<String>,
+    pub category: String,
+    pub confidence: f64,
+    pub fingerprint: String,
+    pub cluster_id: String,
+}
+
+
+impl Item{
+
+    pub fn uid(&self)->String{
+        if !self.uid.is_empty() {
+            return self.uid.clone();
+        }
+        self.make_uid(&UidConfig::default())
+    }
+
+
+    fn field(&self,name:&str)->&str{
+        match name {
+            "title" => self.title.as_str(),
+            "link" => self.link.as_str(),
+            "author" => self.author.as_str(),
+            "description" => self.description.as_str(),
+            "guid" => self.guid.as_str(),
+            "publish" => self.publish.as_str(),
+            _ => ""
+        }
+    }
+
+
+    // `{name}` of the template replaced by the item fields.
+    fn render(&self,template:&str)->String{
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            match rest[start..].find('}') {
+                Some(end) => {
+                    text.push_str(self.field(&rest[start + 1..start + end]));
+                    rest = &rest[start + end + 1..];
+                }
+                None => {
+                    rest = &rest[start..];
+                    break;
+                }
+            }
+        }
+        text.push_str(rest);
+        text
+    }
+
+
+    pub fn make_uid(&self,conf:&UidConfig)->String{
+        let either = |first:&str,second:&str| if !first.is_empty() {
+            first.to_string()
+        }else{
+            second.to_string()
+        };
+
+        let key = match conf.from {
+            UidFrom::Guid => either(self.guid.as_str(),self.link.as_str()),
+            UidFrom::Link => either(self.link.as_str(),self.guid.as_str()),
+            UidFrom::TitlePublish => format!("{}\n{}",self.title,self.publish),
+            // Content hash of the item as the feed sends it.
+            UidFrom::Description => self.description.clone(),
+            UidFrom::Template => self.render(conf.template.as_str()),
+        };
+
+        match conf.hash {
+            UidHash::MD5 => format!("{:x}",md5::compute(key.as_bytes())),
+            UidHash::SHA1 => {
+                use sha1::Digest;
+                hex(sha1::Sha1::digest(key.as_bytes()).as_slice())
+            }
+            UidHash::SHA256 => {
+                use sha2::Digest;
+                hex(sha2::Sha256::digest(key.as_bytes()).as_slice())
+            }
+        }
+    }
+
+    pub fn from_json(obj:&json::JsonValue)->Self{
+        let field = |key:&str| if obj[key].is_string() {
+            obj[key].to_string()
+        }else{
+            String::new()
+        };
+        let list = |key:&str| obj[key].members()
+            .filter(|v| v.is_string())
+            .map(|v| v.to_string())
+            .collect::<Vec<String>>();
+
+        Self{
+            uid: field("uid"),
+            title: field("title"),
+            link: field("link"),
+            author: field("author"),
+            description: field("description"),
+            description_html: field("description_html"),
+            description_text: field("description_text"),
+            guid: field("guid"),
+            publish: field("publish"),
+            content: field("content"),
+            comments: field("comments"),
+            categories: list("categories"),
+            enclosures: obj["enclosures"].members()
+                .map(|e| Enclosure{
+                    url: if e["url"].is_string() { e["url"].to_string() } else { String::new() },
+                    mime_type: if e["type"].is_string() { e["type"].to_string() } else { String::new() },
+                    length: e["length"].as_u64().unwrap_or(0),
+                })
+                .collect(),
+            thumbnails: list("thumbnails"),
+            tags: list("tags"),
+            category: field("category"),
+            confidence: obj["confidence"].as_f64().unwrap_or(0.0),
+            fingerprint: field("fingerprint"),
+            cluster_id: field("cluster_id"),
+        }
+    }
+
+    pub fn to_json(&self)->json::JsonValue{
+        let mut obj = json::JsonValue::new_object();
+        obj["uid"] = self.uid().into();
+        obj["title"] = self.title.clone().into();
+        obj["link"] = self.link.clone().into();
+        obj["author"] = self.author.clone().into();
+        obj["description"] = self.description.clone().into();
+        obj["description_html"] = self.description_html.clone().into();
+        obj["description_text"] = self.description_text.clone().into();
+        obj["guid"] = self.guid.clone().into();
+        obj["publish"] = self.publish.clone().into();
+        obj["content"] = self.content.clone().into();
+        obj["comments"] = self.comments.clone().into();
+        obj["categories"] = self.categories.clone().into();
+        let mut enclosures = json::JsonValue::new_array();
+        self.enclosures.iter().for_each(|e|{
+            let mut enclosure = json::JsonValue::new_object();
+            enclosure["url"] = e.url.clone().into();
+            enclosure["type"] = e.mime_type.clone().into();
+            enclosure["length"] = e.length.into();
+            let _ = enclosures.push(enclosure);
+        });
+        obj["enclosures"] = enclosures;
+        obj["thumbnails"] = self.thumbnails.clone().into();
+        obj["tags"] = self.tags.clone().into();
+        obj["category"] = self.category.clone().into();
+        obj["confidence"] = self.confidence.into();
+        obj["fingerprint"] = self.fingerprint.clone().into();
+        obj["cluster_id"] = self.cluster_id.clone().into();
+        obj
+    }
+}
+
+
+pub fn to_json_string(items:&[Item])->String{
+    let mut list = json::JsonValue::new_array();
+    items.iter().for_each(|item|{
+        let _ = list.push(item.to_json());
+    });
+    list.dump()
+}
+
+
+pub fn from_json_string(data:&str)->Vec<Item>{
+    match json::parse(data) {
+        Ok(list) => list.members().map(Item::from_json).collect(),
+        Err(_) => Vec::new()
+    }
+}
+
+
+pub fn find_json(data:&str,uid:&str)->Option<Item>{
+    let list = json::parse(data).ok()?;
+    list.members()
+        .map(Item::from_json)
+        .find(|item| item.uid() == uid)
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::item;
+
+    fn uid(item:&Item,from:UidFrom,hash:UidHash)->String{
+        item.make_uid(&UidConfig{ from, hash, template: String::new() })
+    }
+
+    #[test]
+    fn uid_sources(){
+        let mut val = Item{ guid: String::from("abc"), link: String::from("https://example.com/a"), ..item("Title","Body") };
+        assert_eq!(uid(&val,UidFrom::Guid,UidHash::MD5),"900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(uid(&val,UidFrom::Link,UidHash::MD5),format!("{:x}",md5::compute("https://example.com/a")));
+        assert_eq!(uid(&val,UidFrom::Description,UidHash::MD5),format!("{:x}",md5::compute("Body")));
+        val.publish = String::from("2020-01-02");
+        assert_eq!(uid(&val,UidFrom::TitlePublish,UidHash::MD5),format!("{:x}",md5::compute("Title\n2020-01-02")));
+
+        // Either falls back to the other.
+        let guid_only = Item{ guid: String::from("abc"), ..Default::default() };
+        assert_eq!(uid(&guid_only,UidFrom::Link,UidHash::MD5),uid(&guid_only,UidFrom::Guid,UidHash::MD5));
+        let link_only = Item{ link: String::from("abc"), ..Default::default() };
+        assert_eq!(uid(&link_only,UidFrom::Guid,UidHash::MD5),"900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(link_only.uid(),"900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(Item{ uid: String::from("kept"), ..link_only }.uid(),"kept");
+    }
+
+    #[test]
+    fn uid_hashes(){
+        let val = Item{ guid: String::from("abc"), ..Default::default() };
+        assert_eq!(uid(&val,UidFrom::Guid,UidHash::SHA1),"a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(uid(&val,UidFrom::Guid,UidHash::SHA256),"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(uid(&val,UidFrom::Guid,UidHash::MD5).len(),32);
+        assert_eq!(uid(&val,UidFrom::Guid,UidHash::SHA1).len(),40);
+        assert_eq!(uid(&val,UidFrom::Guid,UidHash::SHA256).len(),64);
+    }
+
+    #[test]
+    fn render_template(){
+        let val = Item{ author: String::from("me"), publish: String::from("2020"), ..item("Title","") };
+        assert_eq!(val.render("{title}|{publish}"),"Title|2020");
+        assert_eq!(val.render("by {author} {unknown}."),"by me .");
+        assert_eq!(val.render("{title"),"{title");
+        assert_eq!(val.render("plain"),"plain");
+
+        let conf = UidConfig{ from: UidFrom::Template, template: String::from("{title}|{publish}"), hash: UidHash::MD5 };
+        assert_eq!(val.make_uid(&conf),format!("{:x}",md5::compute("Title|2020")));
+    }
+}
@@ -1,4 +1,31 @@
 
 pub mod app;
+pub mod browser;
+pub mod canonical;
+pub mod classify;
+pub mod cluster;
 pub mod config;
-pub mod output;
\ No newline at end of file
+pub mod diff;
+pub mod extras;
+pub mod fetch;
+pub mod filter;
+pub mod health;
+pub mod item;
+pub mod loader;
+pub mod logging;
+pub mod metrics;
+pub mod output;
+pub mod readability;
+pub mod sanitize;
+pub mod scrape;
+pub mod sink;
+pub mod source;
+pub mod tag;
+#[cfg(test)]
+mod testutil;
+pub mod warc;
+
+// Errors of config files, models and archives, the caller adds the context to the message.
+pub(crate) fn throw_err(e:&str)->std::io::Error{
+    std::io::Error::new(std::io::ErrorKind::InvalidData,e)
+}
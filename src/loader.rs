@@ -0,0 +1,495 @@
+use crate::throw_err;
+use serde::de::DeserializeOwned;
+
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Format{
+    Json,
+    Toml,
+    Yaml,
+}
+
+
+pub fn format_of(filename:&str)->Format{
+    let extension = std::path::Path::new(filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "toml" => Format::Toml,
+        "yaml" | "yml" => Format::Yaml,
+        _ => Format::Json
+    }
+}
+
+
+fn line_of(text:&str,offset:usize)->usize{
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+
+// Best effort line lookup for a key path such as `scrape.item` or `urls[2]`:
+// every key of the path is searched after the position of its parent.
+fn locate(text:&str,format:Format,path:&str)->Option<usize>{
+    let mut offset = 0;
+    let mut found = None;
+
+    for segment in path.split(['.','[']) {
+        let key = segment.trim_end_matches(']');
+        if key.is_empty() || key == "?" || key.parse::<usize>().is_ok() {
+            continue;
+        }
+
+        let patterns = match format {
+            Format::Json => vec![format!("\"{}\"",key)],
+            Format::Toml => vec![format!("{} =",key),format!("{}=",key),format!("[{}]",key),format!("\"{}\"",key)],
+            Format::Yaml => vec![format!("{}:",key),format!("\"{}\":",key)],
+        };
+
+        let position = patterns.iter()
+            .filter_map(|p| text[offset..].find(p.as_str()))
+            .min()?;
+        offset += position;
+        found = Some(line_of(text,offset));
+    }
+    found
+}
+
+
+fn describe(filename:&str,line:Option<usize>,path:&str,message:&str)->String{
+    let location = match line {
+        Some(l) => format!("{}:{}",filename,l),
+        None => filename.to_string()
+    };
+    if !path.is_empty() && path != "." {
+        format!("{}: {}: {}",location,path,message)
+    }else{
+        format!("{}: {}",location,message)
+    }
+}
+
+
+pub fn parse_value(filename:&str,text:&str,format:Format)->Result<serde_json::Value,std::io::Error>{
+    match format {
+        Format::Json => serde_json::from_str::<serde_json::Value>(text).map_err(|e|{
+            throw_err(describe(filename,Some(e.line()),"",e.to_string().as_str()).as_str())
+        }),
+
+        Format::Toml => toml::from_str::<serde_json::Value>(text).map_err(|e|{
+            let line = e.span().map(|s| line_of(text,s.start));
+            throw_err(describe(filename,line,"",e.message()).as_str())
+        }),
+
+        Format::Yaml => serde_yaml::from_str::<serde_json::Value>(text).map_err(|e|{
+            let line = e.location().map(|l| l.line());
+            throw_err(describe(filename,line,"",e.to_string().as_str()).as_str())
+        }),
+    }
+}
+
+
+// `${VAR}` / `${VAR:-default}` expansion, `$$` stands for a literal `$`.
+pub fn expand(text:&str)->Result<String,String>{
+    let chars:Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] != '$' || i + 1 >= chars.len() {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        if chars[i+1] == '$' {
+            out.push('$');
+            i += 2;
+            continue;
+        }
+
+        if chars[i+1] != '{' {
+            out.push('$');
+            i += 1;
+            continue;
+        }
+
+        // Find the matching brace, defaults may contain other expansions.
+        let mut depth = 0;
+        let mut end = None;
+        for (j,c) in chars.iter().enumerate().skip(i+1) {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(j);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let end = end.ok_or_else(|| format!("unterminated `${{` in `{}`",text))?;
+        let inner:String = chars[i+2..end].iter().collect();
+        let (name,default) = match inner.find(":-") {
+            Some(p) => (inner[..p].to_string(),Some(inner[p+2..].to_string())),
+            None => (inner.clone(),None)
+        };
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("invalid variable name `{}`",name));
+        }
+
+        match std::env::var(name.as_str()) {
+            Ok(v) if !v.is_empty() || default.is_none() => out.push_str(v.as_str()),
+            _ => match default {
+                Some(d) => out.push_str(expand(d.as_str())?.as_str()),
+                None => return Err(format!("environment variable `{}` is not set",name))
+            }
+        }
+        i = end + 1;
+    }
+    Ok(out)
+}
+
+
+fn join_path(parent:&str,key:&str)->String{
+    if !parent.is_empty() {
+        format!("{}.{}",parent,key)
+    }else{
+        key.to_string()
+    }
+}
+
+
+// Expand every string of the document and replace `<key>_file` entries by the
+// content of the file they point at (relative to the config directory).
+fn resolve(value:&mut serde_json::Value,path:&str,base_dir:&std::path::Path)->Result<(),(String,String)>{
+    match value {
+        serde_json::Value::String(s) => {
+            *s = expand(s.as_str()).map_err(|e|(path.to_string(),e))?;
+        }
+
+        serde_json::Value::Array(list) => {
+            for (i,v) in list.iter_mut().enumerate() {
+                resolve(v,format!("{}[{}]",path,i).as_str(),base_dir)?;
+            }
+        }
+
+        serde_json::Value::Object(obj) => {
+            for (k,v) in obj.iter_mut() {
+                resolve(v,join_path(path,k).as_str(),base_dir)?;
+            }
+
+            let secret_keys:Vec<String> = obj.iter()
+                .filter(|(k,v)| k.ends_with("_file") && k.len() > 5 && v.is_string())
+                .map(|(k,_)| k.clone())
+                .collect();
+
+            for key in secret_keys {
+                let target = key.trim_end_matches("_file").to_string();
+                let key_path = join_path(path,key.as_str());
+                if obj.contains_key(target.as_str()) {
+                    return Err((key_path,format!("conflicts with `{}`, set only one of them",target)));
+                }
+
+                let filename = obj[key.as_str()].as_str().unwrap_or("").to_string();
+                let full_path = base_dir.join(filename.as_str());
+                let secret = std::fs::read_to_string(&full_path)
+                    .map_err(|e|(key_path.clone(),format!("{}: {}",full_path.display(),e)))?;
+
+                obj.remove(key.as_str());
+                obj.insert(target,serde_json::Value::String(secret.trim_end_matches(['\n','\r']).to_string()));
+            }
+        }
+
+        _ => {}
+    }
+    Ok(())
+}
+
+
+fn is_config_file(path:&std::path::Path)->bool{
+    let extension = path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    path.is_file() && ["json","toml","yaml","yml"].contains(&extension.as_str())
+}
+
+
+// Files of a conf.d-style directory, sorted by name: `_*` files are partials
+// shared by every feed of the directory, the other ones are feeds.
+pub fn dir_files(dir:&str)->Result<(Vec<String>,Vec<String>),std::io::Error>{
+    let mut files:Vec<std::path::PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| std::io::Error::new(e.kind(),format!("{}: {}",dir,e)))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| is_config_file(path))
+        .collect();
+    files.sort();
+
+    let (partials,feeds):(Vec<std::path::PathBuf>,Vec<std::path::PathBuf>) = files.into_iter()
+        .partition(|path|{
+            path.file_name()
+                .map(|n| n.to_string_lossy().starts_with('_'))
+                .unwrap_or(false)
+        });
+
+    Ok((
+        partials.iter().map(|p| p.to_string_lossy().to_string()).collect(),
+        feeds.iter().map(|p| p.to_string_lossy().to_string()).collect(),
+    ))
+}
+
+
+// Objects are merged key by key, everything else is replaced by the overlay.
+pub fn merge(base:&mut serde_json::Value,overlay:serde_json::Value){
+    match (base,overlay) {
+        (serde_json::Value::Object(base_obj),serde_json::Value::Object(overlay_obj)) => {
+            for (k,v) in overlay_obj {
+                match base_obj.get_mut(k.as_str()) {
+                    Some(existing) => merge(existing,v),
+                    None => {
+                        base_obj.insert(k,v);
+                    }
+                }
+            }
+        }
+        (base,overlay) => *base = overlay
+    }
+}
+
+
+struct Source{
+    filename: String,
+    text: String,
+    format: Format,
+}
+
+
+pub struct Document{
+    pub filename: String,
+    pub value: serde_json::Value,
+    sources: Vec<Source>,
+}
+
+impl Document{
+
+    pub fn load(filename:&str)->Result<Self,std::io::Error>{
+        Self::load_with(filename,&[])
+    }
+
+
+    // Load a file on top of `bases`, which are merged before its own `extends`.
+    pub fn load_with(filename:&str,bases:&[String])->Result<Self,std::io::Error>{
+        let mut doc = Self{
+            filename: filename.to_string(),
+            value: serde_json::Value::Null,
+            sources: Vec::new(),
+        };
+
+        let mut stack = Vec::new();
+        doc.value = doc.load_merged(filename,bases,&mut stack)?;
+        Ok(doc)
+    }
+
+
+    fn references(&self,filename:&str,value:&mut serde_json::Value,key:&str,base_dir:&std::path::Path)->Result<Vec<String>,std::io::Error>{
+        let entries = match value.as_object_mut().and_then(|obj| obj.remove(key)) {
+            None => return Ok(Vec::new()),
+            Some(serde_json::Value::String(s)) => vec![s],
+            Some(serde_json::Value::Array(list)) if list.iter().all(|v| v.is_string()) => {
+                list.iter().map(|v| v.as_str().unwrap().to_string()).collect()
+            }
+            Some(_) => {
+                let source = self.sources.last().unwrap();
+                return Err(throw_err(describe(
+                    filename,
+                    locate(source.text.as_str(),source.format,key),
+                    key,
+                    "expected a path or a list of paths"
+                ).as_str()));
+            }
+        };
+
+        let mut files = Vec::new();
+        for entry in entries {
+            let path = base_dir.join(entry.as_str());
+            if path.is_dir() {
+                let (partials,feeds) = dir_files(path.to_string_lossy().as_ref())?;
+                files.extend(partials);
+                files.extend(feeds);
+            }else{
+                files.push(path.to_string_lossy().to_string());
+            }
+        }
+        Ok(files)
+    }
+
+
+    fn load_merged(&mut self,filename:&str,bases:&[String],stack:&mut Vec<std::path::PathBuf>)->Result<serde_json::Value,std::io::Error>{
+        let canonical = std::fs::canonicalize(filename)
+            .map_err(|e| std::io::Error::new(e.kind(),format!("{}: {}",filename,e)))?;
+        if stack.contains(&canonical) {
+            return Err(throw_err(format!("{}: extends/include cycle",filename).as_str()));
+        }
+
+        let text = std::fs::read_to_string(filename)
+            .map_err(|e| std::io::Error::new(e.kind(),format!("{}: {}",filename,e)))?;
+        let format = format_of(filename);
+        let mut value = parse_value(filename,text.as_str(),format)?;
+
+        if !value.is_object() {
+            return Err(throw_err(describe(filename,None,"","Failed by Config[expected a table/object].").as_str()));
+        }
+
+        let base_dir = std::path::Path::new(filename)
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default();
+        if let Err((path,message)) = resolve(&mut value,"",base_dir.as_path()) {
+            return Err(throw_err(describe(filename,locate(text.as_str(),format,path.as_str()),path.as_str(),message.as_str()).as_str()));
+        }
+
+        self.sources.push(Source{ filename: filename.to_string(), text, format });
+
+        let mut parents:Vec<String> = bases.to_vec();
+        parents.extend(self.references(filename,&mut value,"extends",base_dir.as_path())?);
+        let includes = self.references(filename,&mut value,"include",base_dir.as_path())?;
+
+        stack.push(canonical);
+        let mut merged = serde_json::Value::Object(serde_json::Map::new());
+        for parent in parents.iter().chain(includes.iter()) {
+            let parent_value = self.load_merged(parent.as_str(),&[],stack)?;
+            merge(&mut merged,parent_value);
+        }
+        merge(&mut merged,value);
+        stack.pop();
+
+        Ok(merged)
+    }
+
+
+    // The first file defining the key wins the location: the feed file, then the files it extends.
+    fn describe_at(&self,path:&str,message:&str)->String{
+        for source in self.sources.iter() {
+            if let Some(line) = locate(source.text.as_str(),source.format,path) {
+                return describe(source.filename.as_str(),Some(line),path,message);
+            }
+        }
+        describe(self.filename.as_str(),None,path,message)
+    }
+
+
+    // Typed deserialization of the merged document, unknown keys are only warned about.
+    pub fn deserialize<T:DeserializeOwned>(&self)->Result<T,std::io::Error>{
+        let mut value = self.value.clone();
+        if let Some(obj) = value.as_object_mut() {
+            obj.remove("$schema");
+        }
+
+        let mut unknown:Vec<String> = Vec::new();
+        let mut callback = |path:serde_ignored::Path| unknown.push(path.to_string());
+        let deserializer = serde_ignored::Deserializer::new(value,&mut callback);
+        let ret:Result<T,_> = serde_path_to_error::deserialize(deserializer);
+
+        unknown.iter().for_each(|path|{
+            tracing::warn!("{}",self.describe_at(path.as_str(),"unknown key, ignored"));
+        });
+
+        ret.map_err(|e|{
+            let path = e.path().to_string();
+            throw_err(self.describe_at(path.as_str(),e.inner().to_string().as_str()).as_str())
+        })
+    }
+
+
+    pub fn error_at(&self,path:&str,message:&str)->std::io::Error{
+        throw_err(self.describe_at(path,message).as_str())
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::temp_dir;
+
+    #[test]
+    fn expand_variables(){
+        std::env::set_var("EASY_RSS_TEST_HOST","db.local");
+        std::env::set_var("EASY_RSS_TEST_EMPTY","");
+        std::env::remove_var("EASY_RSS_TEST_UNSET");
+
+        assert_eq!(expand("mysql://${EASY_RSS_TEST_HOST}/rss").unwrap(),"mysql://db.local/rss");
+        assert_eq!(expand("${EASY_RSS_TEST_UNSET:-${EASY_RSS_TEST_HOST}}").unwrap(),"db.local");
+        assert_eq!(expand("${EASY_RSS_TEST_EMPTY:-fallback}").unwrap(),"fallback");
+        assert_eq!(expand("${EASY_RSS_TEST_EMPTY}").unwrap(),"");
+        assert!(expand("${EASY_RSS_TEST_UNSET}").unwrap_err().contains("EASY_RSS_TEST_UNSET"));
+        assert!(expand("${EASY_RSS_TEST_HOST").is_err());
+        assert!(expand("${BAD-NAME}").is_err());
+    }
+
+    #[test]
+    fn expand_literal_dollar(){
+        assert_eq!(expand("$$").unwrap(),"$");
+        assert_eq!(expand("pa$$word").unwrap(),"pa$word");
+        assert_eq!(expand("$${EASY_RSS_TEST_UNSET}").unwrap(),"${EASY_RSS_TEST_UNSET}");
+        assert_eq!(expand("cost $5 $").unwrap(),"cost $5 $");
+    }
+
+    #[test]
+    fn secret_files(){
+        let dir = temp_dir("secret");
+        std::fs::write(dir.join("url.txt"),"mysql://user:pass@db/rss\n").unwrap();
+
+        let mut value = serde_json::json!({"outputs": [{"driver_url_file": "url.txt", "metrics_path": "x.prom"}]});
+        resolve(&mut value,"",dir.as_path()).unwrap();
+        assert_eq!(value,serde_json::json!({"outputs": [{"driver_url": "mysql://user:pass@db/rss", "metrics_path": "x.prom"}]}));
+
+        let mut value = serde_json::json!({"driver_url": "x", "driver_url_file": "url.txt"});
+        let (path,message) = resolve(&mut value,"",dir.as_path()).unwrap_err();
+        assert_eq!(path,"driver_url_file");
+        assert!(message.contains("conflicts with `driver_url`"));
+
+        let mut value = serde_json::json!({"driver_url_file": "missing.txt"});
+        assert_eq!(resolve(&mut value,"",dir.as_path()).unwrap_err().0,"driver_url_file");
+
+        // `_file` on its own is no secret.
+        let mut value = serde_json::json!({"_file": "url.txt"});
+        resolve(&mut value,"",dir.as_path()).unwrap();
+        assert_eq!(value,serde_json::json!({"_file": "url.txt"}));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn merge_objects_and_replace_the_rest(){
+        let mut base = serde_json::json!({"http": {"timeout": 10, "retries": 2}, "tags": ["a"], "url": "x"});
+        merge(&mut base,serde_json::json!({"http": {"timeout": 30}, "tags": ["b"]}));
+        assert_eq!(base,serde_json::json!({"http": {"timeout": 30, "retries": 2}, "tags": ["b"], "url": "x"}));
+    }
+
+    #[test]
+    fn extends_and_include(){
+        let dir = temp_dir("extends");
+        std::fs::create_dir_all(dir.join("conf.d")).unwrap();
+        std::fs::write(dir.join("base.yaml"),"http:\n  timeout: 10\n  retries: 2\nsave_type: file\n").unwrap();
+        std::fs::write(dir.join("conf.d/_a.yaml"),"tags: [shared]\n").unwrap();
+        std::fs::write(dir.join("conf.d/b.toml"),"save_type = \"redis\"\n").unwrap();
+        std::fs::write(dir.join("feed.yaml"),"extends: base.yaml\ninclude: conf.d\nurl: https://example.com/rss\nhttp:\n  timeout: 30\n").unwrap();
+
+        let doc = Document::load(dir.join("feed.yaml").to_str().unwrap()).unwrap();
+        assert_eq!(doc.value,serde_json::json!({
+            "http": {"timeout": 30, "retries": 2},
+            "save_type": "redis",
+            "tags": ["shared"],
+            "url": "https://example.com/rss"
+        }));
+
+        std::fs::write(dir.join("base.yaml"),"extends: feed.yaml\n").unwrap();
+        let err = Document::load(dir.join("feed.yaml").to_str().unwrap()).err().unwrap();
+        assert!(err.to_string().contains("cycle"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
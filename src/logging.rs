@@ -0,0 +1,52 @@
+use crate::throw_err;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum LogFormat{
+    Text,
+    Json,
+    Logfmt,
+}
+
+impl std::str::FromStr for LogFormat{
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            "logfmt" => Ok(LogFormat::Logfmt),
+            _ => Err(throw_err(format!("Failed by log format `{}`, expected text, json or logfmt.",s).as_str()))
+        }
+    }
+}
+
+
+// Logs only go to stderr, stdout is left to the data ( items, list, status ).
+// `level` is a level or a filter like `info,easy_rss_lib::fetch=debug`.
+pub fn init(level:&str,format:LogFormat)->Result<(),std::io::Error>{
+    let filter = match EnvFilter::try_new(level) {
+        Ok(f) => f,
+        Err(e) => return Err(throw_err(format!("Failed by log level `{}`: {}",level,e).as_str()))
+    };
+    let registry = tracing_subscriber::registry().with(filter);
+
+    let ret = match format {
+        LogFormat::Text => registry
+            .with(tracing_subscriber::fmt::layer()
+                .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()))
+                .with_writer(std::io::stderr))
+            .try_init(),
+        LogFormat::Json => registry
+            .with(tracing_subscriber::fmt::layer().json().with_current_span(true).with_span_list(true).with_writer(std::io::stderr))
+            .try_init(),
+        LogFormat::Logfmt => registry
+            .with(tracing_logfmt::builder().layer().with_writer(std::io::stderr))
+            .try_init(),
+    };
+
+    ret.map_err(|e| throw_err(e.to_string().as_str()))
+}
@@ -0,0 +1,194 @@
+use crate::health::FeedHealth;
+use crate::throw_err;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry};
+use std::sync::OnceLock;
+
+
+pub struct Metrics{
+    registry: Registry,
+    fetch_duration: HistogramVec,
+    http_responses: IntCounterVec,
+    items: IntCounterVec,
+    sink_duration: HistogramVec,
+    sink_errors: IntCounterVec,
+    feed_failures: IntGaugeVec,
+    feed_last_success: IntGaugeVec,
+    captures: IntCounterVec,
+    capture_duration: HistogramVec,
+}
+
+
+static METRICS:OnceLock<Metrics> = OnceLock::new();
+
+
+fn histogram(registry:&Registry,name:&str,help:&str,buckets:Vec<f64>,labels:&[&str])->HistogramVec{
+    let histogram = HistogramVec::new(HistogramOpts::new(name,help).buckets(buckets),labels).unwrap();
+    registry.register(Box::new(histogram.clone())).unwrap();
+    histogram
+}
+
+fn counter(registry:&Registry,name:&str,help:&str,labels:&[&str])->IntCounterVec{
+    let counter = IntCounterVec::new(Opts::new(name,help),labels).unwrap();
+    registry.register(Box::new(counter.clone())).unwrap();
+    counter
+}
+
+fn gauge(registry:&Registry,name:&str,help:&str,labels:&[&str])->IntGaugeVec{
+    let gauge = IntGaugeVec::new(Opts::new(name,help),labels).unwrap();
+    registry.register(Box::new(gauge.clone())).unwrap();
+    gauge
+}
+
+
+fn metrics()->&'static Metrics{
+    METRICS.get_or_init(||{
+        let registry = Registry::new();
+        Metrics{
+            fetch_duration: histogram(
+                &registry,"easy_rss_fetch_duration_seconds","Time to fetch and parse a feed.",
+                vec![0.1,0.25,0.5,1.0,2.5,5.0,10.0,30.0,60.0],&["feed"]
+            ),
+            http_responses: counter(
+                &registry,"easy_rss_http_responses_total","HTTP responses by host and status, status is `error` without a response.",
+                &["host","status"]
+            ),
+            items: counter(
+                &registry,"easy_rss_items_total","Items of the fetched feeds, kind is parsed, filtered, suppressed, new or duplicate.",
+                &["feed","kind"]
+            ),
+            sink_duration: histogram(
+                &registry,"easy_rss_sink_write_duration_seconds","Time to save a batch to a sink.",
+                vec![0.01,0.05,0.1,0.25,0.5,1.0,2.5,5.0,10.0],&["feed","sink"]
+            ),
+            sink_errors: counter(
+                &registry,"easy_rss_sink_errors_total","Batches a sink failed to save.",
+                &["feed","sink"]
+            ),
+            feed_failures: gauge(
+                &registry,"easy_rss_feed_consecutive_failures","Failed runs of the feed in a row.",
+                &["feed"]
+            ),
+            feed_last_success: gauge(
+                &registry,"easy_rss_feed_last_success_timestamp_seconds","Unix time of the last successful run of the feed.",
+                &["feed"]
+            ),
+            captures: counter(
+                &registry,"easy_rss_captures_total","Page captures by output type, result is ok, unchanged or error.",
+                &["type","result"]
+            ),
+            capture_duration: histogram(
+                &registry,"easy_rss_capture_duration_seconds","Time to load and capture a page.",
+                vec![0.5,1.0,2.5,5.0,10.0,30.0,60.0,120.0],&["type"]
+            ),
+            registry,
+        }
+    })
+}
+
+
+pub fn fetch_duration(feed:&str,seconds:f64){
+    metrics().fetch_duration.with_label_values(&[feed]).observe(seconds);
+}
+
+pub fn http_response(url:&str,status:u16){
+    let host = url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+        .unwrap_or_default();
+    let status = if status > 0 { status.to_string() } else { String::from("error") };
+    metrics().http_responses.with_label_values(&[host.as_str(),status.as_str()]).inc();
+}
+
+pub fn items(feed:&str,parsed:usize,new_items:usize,filtered:usize,suppressed:usize){
+    let items = &metrics().items;
+    items.with_label_values(&[feed,"parsed"]).inc_by(parsed as u64);
+    items.with_label_values(&[feed,"new"]).inc_by(new_items as u64);
+    items.with_label_values(&[feed,"filtered"]).inc_by(filtered as u64);
+    items.with_label_values(&[feed,"suppressed"]).inc_by(suppressed as u64);
+    items.with_label_values(&[feed,"duplicate"]).inc_by(parsed.saturating_sub(new_items + filtered + suppressed) as u64);
+}
+
+pub fn sink_write(feed:&str,sink:&str,seconds:f64,failed:bool){
+    metrics().sink_duration.with_label_values(&[feed,sink]).observe(seconds);
+    if failed {
+        metrics().sink_errors.with_label_values(&[feed,sink]).inc();
+    }
+}
+
+pub fn feed_health(health:&FeedHealth){
+    let feed = health.url.as_str();
+    metrics().feed_failures.with_label_values(&[feed]).set(health.consecutive_failures as i64);
+    metrics().feed_last_success.with_label_values(&[feed]).set(health.last_success);
+}
+
+
+// Counted when dropped, `result` stays `ok` unless set otherwise.
+pub struct Capture{
+    output_type: &'static str,
+    started: std::time::Instant,
+    pub result: &'static str,
+}
+
+impl Capture{
+    pub fn start(output_type:&'static str)->Self{
+        Self{
+            output_type,
+            started: std::time::Instant::now(),
+            result: "ok",
+        }
+    }
+}
+
+impl Drop for Capture{
+    fn drop(&mut self){
+        metrics().captures.with_label_values(&[self.output_type,self.result]).inc();
+        metrics().capture_duration
+            .with_label_values(&[self.output_type])
+            .observe(self.started.elapsed().as_secs_f64());
+    }
+}
+
+
+// Prometheus text exposition format.
+pub fn gather()->String{
+    let mut buffer = Vec::new();
+    if let Err(e) = prometheus::TextEncoder::new().encode(&metrics().registry.gather(),&mut buffer) {
+        tracing::error!("Failed by encode metrics: {}",e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+
+// For the node_exporter textfile collector, renamed into place so it is never read half written.
+pub fn write_textfile(path:&str)->Result<(),std::io::Error>{
+    let tmp = format!("{}.tmp",path);
+    std::fs::write(tmp.as_str(),gather().as_bytes())?;
+    std::fs::rename(tmp.as_str(),path)
+}
+
+
+// Serves `/metrics` from a background thread.
+pub fn serve(address:&str)->Result<std::thread::JoinHandle<()>,std::io::Error>{
+    let server = match tiny_http::Server::http(address) {
+        Ok(s) => s,
+        Err(e) => return Err(throw_err(format!("Failed by listen {}: {}",address,e).as_str()))
+    };
+
+    Ok(std::thread::spawn(move ||{
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let header = tiny_http::Header::from_bytes(
+                    &b"Content-Type"[..],
+                    &b"text/plain; version=0.0.4; charset=utf-8"[..]
+                ).unwrap();
+                tiny_http::Response::from_string(gather()).with_header(header)
+            }else{
+                tiny_http::Response::from_string("Not Found").with_status_code(404)
+            };
+
+            if let Err(e) = request.respond(response) {
+                tracing::warn!("Failed by respond metrics: {}",e);
+            }
+        }
+    }))
+}
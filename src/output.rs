@@ -1,8 +1,40 @@
 use crate::config::{OutputType,OutputConfig};
-use headless_chrome::protocol::page::{ScreenshotFormat};
+use crate::diff;
+use crate::metrics;
+use crate::warc::{WarcExchange, WarcWriter};
+use headless_chrome::protocol::cdp::types::Event;
+use headless_chrome::protocol::cdp::{Network, Page};
 use chrono::{DateTime, Local};
+use base64::Engine;
 use std::ops::AddAssign;
-use std::io::Write;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+
+fn type_name(output_type:&OutputType)->&'static str{
+    match output_type {
+        OutputType::None => "none",
+        OutputType::JPEG(_) => "jpeg",
+        OutputType::PNG => "png",
+        OutputType::PDF => "pdf",
+        OutputType::MHTML => "mhtml",
+        OutputType::WARC => "warc",
+    }
+}
+
+
+fn split_headers(headers:&Network::Headers)->Vec<(String,String)>{
+    // DevTools joins repeated headers with a newline.
+    let mut list = Vec::new();
+    if let Some(map) = headers.0.as_ref().and_then(|v| v.as_object()) {
+        map.iter().for_each(|(k,v)|{
+            v.as_str().unwrap_or("").split('\n').for_each(|value|{
+                list.push((k.clone(),value.to_string()));
+            });
+        });
+    }
+    list
+}
 
 
 pub struct Output<'a>{
@@ -17,6 +49,66 @@ impl<'a> Output<'a>{
 
 
 
+    // Compare a screenshot with the last kept capture of the same url.
+    // Returns false when the change stays under the threshold and the capture should be dropped.
+    fn detect_change(&self,url:&str,hash_name:&str,filename:&str,data:&[u8])->bool{
+        let detection = match &self.conf.change_detection {
+            Some(d) => d,
+            None => return true
+        };
+
+        if !std::path::Path::new(detection.state_path.as_str()).exists() {
+            if let Err(e) = std::fs::create_dir_all(detection.state_path.as_str()) {
+                tracing::warn!("Failed by change detection: {}",e);
+                return true;
+            }
+        }
+
+        let current = match diff::to_png(data) {
+            Ok(png) => png,
+            Err(e) => {
+                tracing::warn!("Failed by change detection: {}",e);
+                return true;
+            }
+        };
+
+        let state_file = format!("{}/{}.png",detection.state_path,hash_name);
+        let (score,diff_file) = match std::fs::read(state_file.as_str()) {
+            Ok(previous) => match diff::compare(previous.as_slice(),current.as_slice(),detection.pixel_tolerance) {
+                Ok(ret) => {
+                    if ret.score < detection.threshold {
+                        return false;
+                    }
+
+                    let diff_file = format!("{}.diff.png",filename);
+                    if let Err(e) = std::fs::write(diff_file.as_str(),ret.image.as_slice()) {
+                        tracing::warn!("Failed by change detection: {}",e);
+                    }
+                    (ret.score,diff_file)
+                }
+                Err(e) => {
+                    tracing::warn!("Failed by change detection: {}",e);
+                    (1.0,String::new())
+                }
+            },
+            Err(_) => (1.0,String::new())
+        };
+
+        if let Err(e) = std::fs::write(state_file.as_str(),current.as_slice()) {
+            tracing::warn!("Failed by change detection: {}",e);
+        }
+
+        let mut change = json::JsonValue::new_object();
+        change["url"] = url.into();
+        change["score"] = score.into();
+        change["capture"] = filename.into();
+        change["diff"] = diff_file.into();
+        println!("{}",change.dump());
+        true
+    }
+
+
+
     pub fn run(&mut self)->Result<(), std::io::Error>{
         let chrome_path = std::path::PathBuf::from(
             self.conf.chrome_path.as_str()
@@ -35,47 +127,152 @@ impl<'a> Output<'a>{
             .idle_browser_timeout(idle_browser_timeout)
             .window_size(Some((window_size_width,window_size_height)))
             .build()
-            .unwrap();
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput,e.to_string()))?;
 
 
-        let list = vec![
-            "https://www.baidu.com",
-            "https://cn.bing.com",
-            "https://www.meteorcat.com/#/"
-        ];
+        let list = self.conf.urls.clone();
+        let exchanges:Arc<Mutex<Vec<WarcExchange>>> = Arc::new(Mutex::new(Vec::new()));
 
 
         match headless_chrome::Browser::new(browser_builder) {
             Ok(browser) =>{
-                match browser.wait_for_initial_tab() {
+                match browser.new_tab() {
                     Ok(tab) =>{
 
+                        if self.conf.output_type == OutputType::WARC {
+                            // Method of every request id, a redirect keeps the id of the request it answers.
+                            let methods:Arc<Mutex<HashMap<String,String>>> = Arc::new(Mutex::new(HashMap::new()));
+                            let recorder = exchanges.clone();
+                            let requests = methods.clone();
+                            let handler = tab.register_response_handling("warc",Box::new(move |params,fetch_body|{
+                                let (request_id,response) = (params.request_id,params.response);
+                                // An empty body under the original status would replay as a truncated page.
+                                let body = match fetch_body() {
+                                    Ok(ret) if ret.base_64_encoded => {
+                                        match base64::engine::general_purpose::STANDARD.decode(ret.body.as_bytes()) {
+                                            Ok(body) => body,
+                                            Err(e) => {
+                                                tracing::warn!(url = %response.url,"Failed by response body, not archived: {}",e);
+                                                return;
+                                            }
+                                        }
+                                    },
+                                    Ok(ret) => ret.body.into_bytes(),
+                                    Err(e) => {
+                                        tracing::warn!(url = %response.url,"Failed by response body, not archived: {}",e);
+                                        return;
+                                    }
+                                };
+                                let method = requests.lock().ok()
+                                    .and_then(|m| m.get(request_id.as_str()).cloned())
+                                    .unwrap_or_else(|| String::from("GET"));
+
+                                let exchange = WarcExchange{
+                                    url: response.url.clone(),
+                                    method,
+                                    request_headers: response.request_headers
+                                        .as_ref()
+                                        .map(split_headers)
+                                        .unwrap_or_default(),
+                                    status: response.status,
+                                    status_text: response.status_text.clone(),
+                                    response_headers: split_headers(&response.headers),
+                                    mime_type: response.mime_type.clone(),
+                                    body,
+                                    date: chrono::Utc::now(),
+                                };
+
+                                if let Ok(mut list) = recorder.lock() {
+                                    list.push(exchange);
+                                }
+                            }));
+
+                            if let Err(e) = handler {
+                                return Err(std::io::Error::other(e.to_string()))
+                            }
+
+                            // Redirect responses never reach the response handler, they come with the next request.
+                            let recorder = exchanges.clone();
+                            let listener = tab.add_event_listener(Arc::new(move |event:&Event|{
+                                let sent = match event {
+                                    Event::NetworkRequestWillBeSent(e) => &e.params,
+                                    _ => return
+                                };
+                                let mut methods = match methods.lock() {
+                                    Ok(m) => m,
+                                    Err(_) => return
+                                };
+                                let previous = methods.insert(sent.request_id.clone(),sent.request.method.clone());
+
+                                if let Some(response) = &sent.redirect_response {
+                                    let exchange = WarcExchange{
+                                        url: response.url.clone(),
+                                        method: previous.unwrap_or_else(|| String::from("GET")),
+                                        request_headers: response.request_headers
+                                            .as_ref()
+                                            .map(split_headers)
+                                            .unwrap_or_default(),
+                                        status: response.status,
+                                        status_text: response.status_text.clone(),
+                                        response_headers: split_headers(&response.headers),
+                                        mime_type: response.mime_type.clone(),
+                                        body: Vec::new(),
+                                        date: chrono::Utc::now(),
+                                    };
+                                    if let Ok(mut list) = recorder.lock() {
+                                        list.push(exchange);
+                                    }
+                                }
+                            }));
+
+                            if let Err(e) = listener {
+                                return Err(std::io::Error::other(e.to_string()))
+                            }
+                        }
+
                         list.iter().for_each(|url|{
                             let url_str = url.to_string();
+                            let _span = tracing::info_span!("capture",url = %url_str).entered();
+                            let mut capture = metrics::Capture::start(type_name(&self.conf.output_type));
                             let hash_name = format!("{:x}",md5::compute(url_str.as_bytes()));
                             let pathname = self.conf.output_path.clone();
 
+                            if let Ok(mut recorded) = exchanges.lock() {
+                                recorded.clear();
+                            }
+
                             if let Err(e) = tab.navigate_to(url_str.as_str()) {
-                                eprintln!("{}",e.to_string());
+                                tracing::error!("{}",e);
+                                capture.result = "error";
                                 return ;
                             }
 
-                            let mut filename = if self.conf.append_date.len() > 0 {
+                            if self.conf.output_type == OutputType::MHTML || self.conf.output_type == OutputType::WARC {
+                                if let Err(e) = tab.wait_until_navigated() {
+                                    tracing::error!("{}",e);
+                                    capture.result = "error";
+                                    return ;
+                                }
+                            }
+
+                            let mut filename = if !self.conf.append_date.is_empty() {
                                 let now_date = self.now.format(self.conf.append_date.as_str()).to_string();
                                 let path = format!("{}/{}",pathname,now_date);
 
-                                if !std::path::Path::new(path.as_str()).exists() {
-                                    let pathname_clone = path.clone();
-                                    std::fs::create_dir_all(pathname_clone.as_str()).unwrap();
-                                };
+                                if let Err(e) = std::fs::create_dir_all(path.as_str()) {
+                                    tracing::error!("{}",e);
+                                    capture.result = "error";
+                                    return ;
+                                }
 
                                 format!("{}/{}/{}",pathname,now_date,hash_name)
                             }else{
 
-                                if !std::path::Path::new(pathname.as_str()).exists() {
-                                    let pathname_clone = pathname.clone();
-                                    std::fs::create_dir_all(pathname_clone.as_str()).unwrap();
-                                };
+                                if let Err(e) = std::fs::create_dir_all(pathname.as_str()) {
+                                    tracing::error!("{}",e);
+                                    capture.result = "error";
+                                    return ;
+                                }
                                 format!("{}/{}",pathname,hash_name)
                             };
 
@@ -85,17 +282,15 @@ impl<'a> Output<'a>{
                                     match tab.print_to_pdf(None) {
                                         Ok(pdf) =>{
                                             filename.add_assign(".pdf");
-                                            let mut fd = std::fs::OpenOptions::new()
-                                                .write(true)
-                                                .create(true)
-                                                .open(filename)
-                                                .unwrap();
-
-                                            fd.write_all(pdf.as_slice()).unwrap();
+                                            if let Err(e) = std::fs::write(filename.as_str(),pdf.as_slice()) {
+                                                tracing::error!("{}",e);
+                                                capture.result = "error";
+                                            }
                                         }
 
                                         Err(e)=>{
-                                            eprintln!("{}",e.to_string());
+                                            tracing::error!("{}",e);
+                                            capture.result = "error";
                                         }
 
                                     }
@@ -103,22 +298,26 @@ impl<'a> Output<'a>{
 
                                 OutputType::PNG => {
                                     match tab.capture_screenshot(
-                                        ScreenshotFormat::PNG,
+                                        Page::CaptureScreenshotFormatOption::Png,
+                                        None,
                                         None,
                                         true) {
                                         Ok(png) =>{
                                             filename.add_assign(".png");
-                                            let mut fd = std::fs::OpenOptions::new()
-                                                .write(true)
-                                                .create(true)
-                                                .open(filename)
-                                                .unwrap();
-
-                                            fd.write_all(png.as_slice()).unwrap();
+                                            if !self.detect_change(url_str.as_str(),hash_name.as_str(),filename.as_str(),png.as_slice()) {
+                                                capture.result = "unchanged";
+                                                return ;
+                                            }
+
+                                            if let Err(e) = std::fs::write(filename.as_str(),png.as_slice()) {
+                                                tracing::error!("{}",e);
+                                                capture.result = "error";
+                                            }
                                         }
 
                                         Err(e)=>{
-                                            eprintln!("{}",e.to_string());
+                                            tracing::error!("{}",e);
+                                            capture.result = "error";
                                         }
 
                                     }
@@ -126,34 +325,84 @@ impl<'a> Output<'a>{
 
                                 OutputType::JPEG(quality) =>{
                                     match tab.capture_screenshot(
-                                        ScreenshotFormat::JPEG(Some(quality)),
+                                        Page::CaptureScreenshotFormatOption::Jpeg,
+                                        Some(quality),
                                         None,
                                         true) {
 
                                         Ok(jpeg) => {
                                             filename.add_assign(".jpeg");
-                                            let mut fd = std::fs::OpenOptions::new()
-                                                .write(true)
-                                                .create(true)
-                                                .open(filename)
-                                                .unwrap();
+                                            if !self.detect_change(url_str.as_str(),hash_name.as_str(),filename.as_str(),jpeg.as_slice()) {
+                                                capture.result = "unchanged";
+                                                return ;
+                                            }
+
+                                            if let Err(e) = std::fs::write(filename.as_str(),jpeg.as_slice()) {
+                                                tracing::error!("{}",e);
+                                                capture.result = "error";
+                                            }
+                                        }
+
+                                        Err(e) =>{
+                                            tracing::error!("{}",e);
+                                            capture.result = "error";
+                                        }
+                                    }
+                                }
 
-                                            fd.write_all(jpeg.as_slice()).unwrap();
+                                OutputType::MHTML => {
+                                    match tab.call_method(Page::CaptureSnapshot{ format: Some(Page::CaptureSnapshotFormatOption::Mhtml) }) {
+                                        Ok(snapshot) => {
+                                            filename.add_assign(".mhtml");
+                                            if let Err(e) = std::fs::write(filename.as_str(),snapshot.data.as_bytes()) {
+                                                tracing::error!("{}",e);
+                                                capture.result = "error";
+                                            }
                                         }
 
                                         Err(e) =>{
-                                            eprintln!("{}",e.to_string());
+                                            tracing::error!("{}",e);
+                                            capture.result = "error";
                                         }
                                     }
                                 }
 
+                                OutputType::WARC => {
+                                    let recorded = match exchanges.lock() {
+                                        Ok(mut list) => list.drain(..).collect::<Vec<WarcExchange>>(),
+                                        Err(e) => {
+                                            tracing::error!("{}",e);
+                                            capture.result = "error";
+                                            return ;
+                                        }
+                                    };
+
+                                    let warc_name = format!("{}.warc",filename);
+                                    let cdx_name = format!("{}.cdx",filename);
+                                    let written = WarcWriter::create(warc_name.as_str(),cdx_name.as_str())
+                                        .and_then(|mut writer|{
+                                            writer.write_info(
+                                                format!("easy-rss-output/{}",env!("CARGO_PKG_VERSION")).as_str()
+                                            )?;
+                                            for exchange in recorded.iter() {
+                                                writer.write_exchange(exchange)?;
+                                            }
+                                            writer.finish()
+                                        });
+
+                                    if let Err(e) = written {
+                                        tracing::error!("{}",e);
+                                        capture.result = "error";
+                                    }
+                                }
+
                                 _ => {},
                             }
 
                         });
                     },
                     Err(e)=>{
-                        return Err(std::io::Error::new(std::io::ErrorKind::Other,e.to_string()))
+                        return Err(std::io::Error::other(e.to_string()))
                     }
                 }
 
@@ -165,9 +414,56 @@ impl<'a> Output<'a>{
                  */
             }
             Err(e) =>{
-                return Err(std::io::Error::new(std::io::ErrorKind::Other,e.to_string()))
+                return Err(std::io::Error::other(e.to_string()))
             }
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::config::ChangeDetection;
+    use crate::testutil::{png, temp_dir};
+
+    #[test]
+    fn change_under_threshold_is_dropped(){
+        let dir = temp_dir("change");
+        let conf = OutputConfig{
+            chrome_path: String::new(),
+            headless: true,
+            sandbox: true,
+            idle_browser_timeout: std::time::Duration::from_secs(1),
+            window_size_width: 4,
+            window_size_height: 4,
+            output_type: OutputType::PNG,
+            output_path: dir.to_str().unwrap().to_string(),
+            append_date: String::new(),
+            urls: Vec::new(),
+            change_detection: Some(ChangeDetection{
+                state_path: dir.join("state").to_str().unwrap().to_string(),
+                threshold: 0.5,
+                pixel_tolerance: 0,
+            }),
+            metrics_path: String::new(),
+        };
+        let output = Output::from(&conf);
+        let capture = |name:&str| dir.join(name).to_str().unwrap().to_string();
+        let white = png(4,4,|_,_| [255,255,255]);
+
+        // The first capture has nothing to compare with.
+        assert!(output.detect_change("https://example.com/","a",capture("1.png").as_str(),white.as_slice()));
+        // A quarter of the pixels is under the threshold, the kept state stays the first capture.
+        let quarter = png(4,4,|x,_| if x == 0 { [0,0,0] } else { [255,255,255] });
+        assert!(!output.detect_change("https://example.com/","a",capture("2.png").as_str(),quarter.as_slice()));
+        assert!(!dir.join("2.png.diff.png").exists());
+
+        let half = png(4,4,|x,_| if x < 2 { [0,0,0] } else { [255,255,255] });
+        assert!(output.detect_change("https://example.com/","a",capture("3.png").as_str(),half.as_slice()));
+        assert!(dir.join("3.png.diff.png").exists());
+        // Compared with the last kept capture now.
+        assert!(!output.detect_change("https://example.com/","a",capture("4.png").as_str(),half.as_slice()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,182 @@
+use scraper::{ElementRef, Html, Node, Selector};
+use std::collections::HashMap;
+
+
+#[derive(Debug,Clone)]
+pub struct Article{
+    pub html: String,
+    pub text: String,
+}
+
+
+const POSITIVE_HINTS:[&str;12] = [
+    "article", "body", "content", "entry", "hentry", "main",
+    "page", "post", "text", "blog", "story", "detail",
+];
+
+const NEGATIVE_HINTS:[&str;16] = [
+    "comment", "footer", "footnote", "sidebar", "nav", "menu",
+    "share", "social", "related", "recommend", "promo", "sponsor",
+    "banner", "advert", "header", "meta",
+];
+
+const SKIP_TAGS:[&str;10] = [
+    "script", "style", "noscript", "iframe", "form",
+    "button", "input", "select", "textarea", "svg",
+];
+
+const VOID_TAGS:[&str;8] = [
+    "br", "hr", "img", "source", "wbr", "col", "area", "track",
+];
+
+
+fn class_weight(el:&ElementRef)->f64{
+    let mut weight = 0.0;
+    let hints = format!(
+        "{} {}",
+        el.value().attr("class").unwrap_or(""),
+        el.value().attr("id").unwrap_or("")
+    ).to_lowercase();
+
+    if hints.trim().is_empty() {
+        return weight;
+    }
+    if POSITIVE_HINTS.iter().any(|h| hints.contains(h)) {
+        weight += 25.0;
+    }
+    if NEGATIVE_HINTS.iter().any(|h| hints.contains(h)) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+
+fn tag_weight(name:&str)->f64{
+    match name {
+        "article" => 10.0,
+        "div" => 5.0,
+        "section" | "main" => 3.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" | "header" | "footer" | "nav" | "aside" => -5.0,
+        _ => 0.0
+    }
+}
+
+
+fn inner_text(el:&ElementRef)->String{
+    let mut text = String::new();
+    el.descendants().for_each(|node|{
+        if let Node::Text(t) = node.value() {
+            let skipped = node.ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(|p| SKIP_TAGS.contains(&p.value().name()));
+            if !skipped {
+                text.push_str(t);
+            }
+        }
+    });
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+
+fn link_density(el:&ElementRef,text_length:usize)->f64{
+    if text_length == 0 {
+        return 0.0;
+    }
+    let selector = Selector::parse("a").unwrap();
+    let link_length:usize = el.select(&selector)
+        .map(|a| inner_text(&a).chars().count())
+        .sum();
+    link_length as f64 / text_length as f64
+}
+
+
+fn escape(text:&str,attribute:bool)->String{
+    let mut out = String::with_capacity(text.len());
+    text.chars().for_each(|c|{
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attribute => out.push_str("&quot;"),
+            _ => out.push(c)
+        }
+    });
+    out
+}
+
+
+fn write_html(node:ego_tree::NodeRef<Node>,out:&mut String){
+    match node.value() {
+        Node::Text(t) => out.push_str(escape(t,false).as_str()),
+        Node::Element(el) => {
+            let name = el.name();
+            if SKIP_TAGS.contains(&name) {
+                return;
+            }
+            out.push('<');
+            out.push_str(name);
+            el.attrs().for_each(|(k,v)|{
+                out.push_str(format!(" {}=\"{}\"",k,escape(v,true)).as_str());
+            });
+            out.push('>');
+            if VOID_TAGS.contains(&name) {
+                return;
+            }
+            node.children().for_each(|child| write_html(child,out));
+            out.push_str(format!("</{}>",name).as_str());
+        },
+        _ => {}
+    }
+}
+
+
+// A readability-style extractor: paragraphs vote for their parent and grand parent,
+// the best scored container (penalized by its link density) is taken as the article body.
+pub fn extract(html:&str)->Option<Article>{
+    let document = Html::parse_document(html);
+    let paragraphs = Selector::parse("p, pre, td, section > div, article > div").unwrap();
+
+    let mut scores:HashMap<ego_tree::NodeId,f64> = HashMap::new();
+    document.select(&paragraphs).for_each(|p|{
+        let text = inner_text(&p);
+        let length = text.chars().count();
+        if length < 25 {
+            return;
+        }
+
+        let commas = text.chars().filter(|c| *c == ',' || *c == '，' || *c == '、').count();
+        let score = 1.0 + commas as f64 + ((length / 100) as f64).min(3.0);
+
+        let parent = p.parent().and_then(ElementRef::wrap);
+        let grand_parent = parent.and_then(|el| el.parent()).and_then(ElementRef::wrap);
+
+        if let Some(el) = parent {
+            let entry = scores.entry(el.id())
+                .or_insert_with(|| tag_weight(el.value().name()) + class_weight(&el));
+            *entry += score;
+        }
+        if let Some(el) = grand_parent {
+            let entry = scores.entry(el.id())
+                .or_insert_with(|| tag_weight(el.value().name()) + class_weight(&el));
+            *entry += score / 2.0;
+        }
+    });
+
+    let best = scores.into_iter()
+        .filter_map(|(id,score)|{
+            let el = document.tree.get(id).and_then(ElementRef::wrap)?;
+            let length = inner_text(&el).chars().count();
+            Some((el, score * (1.0 - link_density(&el,length))))
+        })
+        .max_by(|a,b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))?;
+
+    let mut article_html = String::new();
+    write_html(*best.0,&mut article_html);
+
+    Some(Article{
+        html: article_html,
+        text: inner_text(&best.0),
+    })
+}
@@ -0,0 +1,178 @@
+use crate::config::SanitizeConfig;
+use scraper::{Html, Node, Selector};
+use std::collections::{HashMap, HashSet};
+
+
+const BLOCK_TAGS:[&str;20] = [
+    "p", "div", "br", "li", "ul", "ol", "h1", "h2", "h3", "h4", "h5", "h6",
+    "blockquote", "pre", "table", "tr", "section", "article", "figure", "hr",
+];
+
+
+fn on_host(host:&str,pattern:&str)->bool{
+    host == pattern || host.ends_with(format!(".{}",pattern).as_str())
+}
+
+
+// Sanitized HTML and plain text of the feed's description.
+pub struct Sanitizer{
+    conf: SanitizeConfig,
+    base: Option<url::Url>,
+}
+
+impl Sanitizer{
+
+    pub fn new(conf:&SanitizeConfig,feed_url:&str)->Self{
+        Self{
+            conf: conf.clone(),
+            base: url::Url::parse(feed_url).ok(),
+        }
+    }
+
+
+    // 1x1 images, hidden images and images of tracker hosts.
+    fn tracking(&self,el:&scraper::node::Element)->bool{
+        let tiny = |name:&str| el.attr(name)
+            .map(|v| v.trim().trim_end_matches("px").parse::<u32>().map(|n| n <= 1).unwrap_or(false))
+            .unwrap_or(false);
+        if tiny("width") || tiny("height") {
+            return true;
+        }
+
+        let style = el.attr("style").unwrap_or("").replace(' ',"").to_lowercase();
+        if style.contains("display:none") || style.contains("visibility:hidden") {
+            return true;
+        }
+
+        let src = el.attr("src").unwrap_or("");
+        let host = match &self.base {
+            Some(base) => base.join(src).ok(),
+            None => url::Url::parse(src).ok()
+        }.and_then(|u| u.host_str().map(|h| h.to_lowercase()));
+        match host {
+            Some(host) => self.conf.tracker_hosts.iter().any(|t| on_host(host.as_str(),t.as_str())),
+            None => false
+        }
+    }
+
+
+    fn without_trackers(&self,html:&str)->String{
+        let mut document = Html::parse_fragment(html);
+        let selector = Selector::parse("img").unwrap();
+        let trackers:Vec<ego_tree::NodeId> = document.select(&selector)
+            .filter(|img| self.tracking(img.value()))
+            .map(|img| img.id())
+            .collect();
+        if trackers.is_empty() {
+            return html.to_string();
+        }
+
+        for id in trackers {
+            if let Some(mut node) = document.tree.get_mut(id) {
+                node.detach();
+            }
+        }
+        document.root_element().inner_html()
+    }
+
+
+    // Relative urls are resolved against `link` ( or the feed url ), scripts and styles are dropped with their content.
+    pub fn html(&self,html:&str,link:&str)->String{
+        let base = url::Url::parse(link).ok().or_else(|| self.base.clone());
+        let html = self.without_trackers(html);
+
+        let mut builder = ammonia::Builder::default();
+        if !self.conf.tags.is_empty() {
+            builder.tags(self.conf.tags.iter().map(|t| t.as_str()).collect::<HashSet<&str>>());
+        }
+        if !self.conf.attributes.is_empty() {
+            let mut attributes:HashMap<&str,HashSet<&str>> = HashMap::new();
+            let mut generic:HashSet<&str> = HashSet::new();
+            for (tag,names) in self.conf.attributes.iter() {
+                let names = names.iter().map(|n| n.as_str());
+                if tag == "*" {
+                    generic.extend(names);
+                }else{
+                    attributes.entry(tag.as_str()).or_default().extend(names);
+                }
+            }
+            builder.tag_attributes(attributes);
+            builder.generic_attributes(generic);
+        }
+        match base {
+            Some(base) => builder.url_relative(ammonia::UrlRelative::RewriteWithBase(base)),
+            None => builder.url_relative(ammonia::UrlRelative::Deny)
+        };
+        builder.clean(html.as_str()).to_string()
+    }
+}
+
+
+// Text of the HTML with entities decoded, a line per block and collapsed spaces.
+pub fn text(html:&str)->String{
+    let document = Html::parse_fragment(html);
+    let mut text = String::new();
+    for edge in document.root_element().traverse() {
+        let (node,open) = match edge {
+            ego_tree::iter::Edge::Open(node) => (node,true),
+            ego_tree::iter::Edge::Close(node) => (node,false)
+        };
+        match node.value() {
+            Node::Text(t) if open => text.push_str(t),
+            Node::Element(el) if BLOCK_TAGS.contains(&el.name()) => text.push('\n'),
+            _ => {}
+        }
+    }
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn sanitizer()->Sanitizer{
+        Sanitizer::new(&SanitizeConfig::default(),"https://example.com/feed/rss.xml")
+    }
+
+    #[test]
+    fn tracking_pixels_are_dropped(){
+        let html = sanitizer().html(concat!(
+            r#"<p>Hi<img src="https://pixel.wp.com/g.gif"><img src="https://i0.stats.wordpress.com/x.gif">"#,
+            r#"<img src="/a.png" width="1" height="1"><img src="/b.png" height="0px">"#,
+            r#"<img src="/c.png" style="display: none"><img src="/d.png" style="Visibility:Hidden">"#,
+            r#"<img src="/photo.png" width="100"></p>"#,
+        ),"https://example.com/post/1");
+        assert_eq!(html,r#"<p>Hi<img src="https://example.com/photo.png" width="100"></p>"#);
+    }
+
+    #[test]
+    fn relative_urls(){
+        let html = sanitizer().html(r#"<a href="../other">x</a><img src="i.png">"#,"https://example.com/post/1/");
+        assert!(html.contains(r#"href="https://example.com/post/other""#));
+        assert!(html.contains(r#"src="https://example.com/post/1/i.png""#));
+
+        // Without a link the feed url is the base, without both relative urls are dropped.
+        assert!(sanitizer().html(r#"<img src="i.png">"#,"").contains(r#"src="https://example.com/feed/i.png""#));
+        let none = Sanitizer::new(&SanitizeConfig::default(),"");
+        assert_eq!(none.html(r#"<img src="i.png" alt="x">"#,""),r#"<img alt="x">"#);
+    }
+
+    #[test]
+    fn scripts_and_handlers_are_dropped(){
+        let html = sanitizer().html(r#"<script>alert(1)</script><p onclick="x()" style="color:red">a</p>"#,"");
+        assert_eq!(html,"<p>a</p>");
+    }
+
+    #[test]
+    fn text_of_blocks(){
+        assert_eq!(text("<p>A &amp; B</p><div>C&nbsp;&lt;D&gt;</div><ul><li>one</li><li> two  words </li></ul>"),"A & B\nC <D>\none\ntwo words");
+        assert_eq!(text("a<br>b <b>bold</b>"),"a\nb bold");
+        assert_eq!(text(""),"");
+    }
+}
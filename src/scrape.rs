@@ -0,0 +1,136 @@
+use crate::config::ScrapeConfig;
+use crate::item::Item;
+use crate::throw_err;
+use scraper::{ElementRef, Html, Selector};
+
+
+fn selector(rule:&str,key:&str)->Result<Option<Selector>,std::io::Error>{
+    if rule.trim().is_empty() {
+        return Ok(None);
+    }
+    match Selector::parse(rule) {
+        Ok(s) => Ok(Some(s)),
+        Err(e) => Err(throw_err(format!("Failed by Config[scrape.{}]: {:?}",key,e).as_str()))
+    }
+}
+
+
+fn select_text(el:&ElementRef,selector:&Option<Selector>)->String{
+    let found = match selector {
+        Some(s) => el.select(s).next(),
+        None => None
+    };
+    match found {
+        Some(node) => node.text().collect::<Vec<&str>>().join(" ").split_whitespace().collect::<Vec<&str>>().join(" "),
+        None => String::new()
+    }
+}
+
+
+fn select_link(el:&ElementRef,selector:&Option<Selector>,base:&Option<url::Url>)->String{
+    let anchor = Selector::parse("a[href]").unwrap();
+    let found = match selector {
+        Some(s) => el.select(s).next(),
+        None => el.select(&anchor).next()
+    };
+
+    let href = match found {
+        Some(node) => match node.value().attr("href") {
+            Some(h) => h.to_string(),
+            None => node.select(&anchor).next()
+                .and_then(|a| a.value().attr("href"))
+                .unwrap_or("")
+                .to_string()
+        },
+        None => String::new()
+    };
+
+    match base {
+        Some(b) if !href.is_empty() => b.join(href.trim())
+            .map(|u| u.to_string())
+            .unwrap_or(href),
+        _ => href
+    }
+}
+
+
+// Build items out of an HTML page, one per `item` container.
+pub fn scrape(html:&str,page_url:&str,conf:&ScrapeConfig)->Result<Vec<Item>,std::io::Error>{
+    let item = match selector(conf.item.as_str(),"item")? {
+        Some(s) => s,
+        None => return Err(throw_err("Failed by Config[scrape.item]."))
+    };
+    let title = selector(conf.title.as_str(),"title")?;
+    let link = selector(conf.link.as_str(),"link")?;
+    let date = selector(conf.date.as_str(),"date")?;
+    let author = selector(conf.author.as_str(),"author")?;
+    let summary = selector(conf.summary.as_str(),"summary")?;
+
+    let base = url::Url::parse(page_url).ok();
+    let document = Html::parse_document(html);
+
+    let data = document.select(&item)
+        .map(|el|{
+            let link = select_link(&el,&link,&base);
+            Item{
+                title: select_text(&el,&title),
+                guid: link.clone(),
+                link,
+                author: select_text(&el,&author),
+                description: match &summary {
+                    Some(s) => el.select(s).next().map(|n| n.inner_html()).unwrap_or_default(),
+                    None => String::new()
+                },
+                publish: select_text(&el,&date),
+                ..Default::default()
+            }
+        })
+        .filter(|i| !i.title.is_empty() || !i.link.is_empty())
+        .collect();
+
+    Ok(data)
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn conf(json:&str)->ScrapeConfig{
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn items_of_containers(){
+        let html = r#"<ul>
+            <li class="post"><a href="/p/1"> First
+                post </a><span class="by">Ann</span><div class="sum"><b>Hi</b></div><time>2020-01-02</time></li>
+            <li class="post"><span class="by">Bob</span></li>
+            <li class="post"><h2>Only a title</h2></li>
+            <li class="post"><h2><a href=" ../p/2 ">Second</a></h2></li>
+        </ul>"#;
+        let rules = conf(r#"{"item":"li.post","title":"a, h2","link":"h2","author":".by","summary":".sum","date":"time"}"#);
+        let items = scrape(html,"https://example.com/list/index.html",&rules).unwrap();
+
+        assert_eq!(items.len(),3);
+        // The link selector finds no h2 in the first container.
+        assert_eq!((items[0].title.as_str(),items[0].link.as_str()),("First post",""));
+        assert_eq!(items[0].author,"Ann");
+        assert_eq!(items[0].description,"<b>Hi</b>");
+        assert_eq!(items[0].publish,"2020-01-02");
+        assert_eq!((items[1].title.as_str(),items[1].link.as_str()),("Only a title",""));
+        // A container without href falls back to its first link, resolved against the page.
+        assert_eq!(items[2].link,"https://example.com/p/2");
+        assert_eq!(items[2].guid,items[2].link);
+
+        let default_link = scrape(html,"https://example.com/list/",&conf(r#"{"item":"li.post"}"#)).unwrap();
+        assert_eq!(default_link.iter().map(|i| i.link.as_str()).collect::<Vec<&str>>(),vec!["https://example.com/p/1","https://example.com/p/2"]);
+    }
+
+    #[test]
+    fn bad_selectors(){
+        assert!(scrape("","",&conf("{}")).unwrap_err().to_string().contains("scrape.item"));
+        assert!(scrape("","",&conf(r#"{"item":"li["}"#)).unwrap_err().to_string().contains("scrape.item"));
+        assert!(scrape("","",&conf(r#"{"item":"li","title":"::"}"#)).unwrap_err().to_string().contains("scrape.title"));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::config::{CliConfig, OutputTarget, SaveType};
+use crate::item::Item;
+use chrono::{DateTime, Local};
+
+mod file;
+mod mysql;
+mod redis;
+mod stdout;
+
+pub use self::file::FileSink;
+pub use self::mysql::MySqlSink;
+pub use self::redis::RedisSink;
+pub use self::stdout::StdoutSink;
+
+
+#[derive(Debug,Clone,Default)]
+pub struct SinkReport{
+    pub sink: String,
+    pub target: String,
+    pub saved: usize,
+    pub uids: Vec<String>,
+    // Items the sink could not write, the batch itself went through.
+    pub failed: usize,
+    pub attempts: u32,
+    pub error: Option<String>,
+}
+
+
+// Where items go: persist a parsed batch and report what was written.
+pub trait Sink{
+    fn name(&self)->String;
+
+    fn save(&mut self,items:&[Item])->Result<SinkReport,std::io::Error>;
+
+    // Whether the item is already stored, new items are the only ones worth enriching.
+    fn contains(&mut self,_uid:&str)->Result<bool,std::io::Error>{
+        Ok(false)
+    }
+
+    fn find(&mut self,_uid:&str)->Result<Option<Item>,std::io::Error>{
+        Ok(None)
+    }
+
+    // Every stored item, read back for `easy-rss-cli export`.
+    fn export(&mut self)->Result<Vec<Item>,std::io::Error>{
+        Ok(Vec::new())
+    }
+
+    // Items this sink saves, the others of a batch are skipped by `save`.
+    fn accepts(&self,_item:&Item)->bool{
+        true
+    }
+}
+
+
+// `table_name` with the `append_date` suffix of the current run, e.g. rss_zhihu_2020_01_01.
+pub fn dated_name(name:&str,append_date:&str,now:&DateTime<Local>)->String{
+    if !append_date.is_empty() {
+        format!("{}_{}",name,now.format(append_date))
+    }else{
+        name.to_string()
+    }
+}
+
+
+// Whether `name` is `table_name` itself or dated by `append_date`, e.g. rss_zhihu_2020_01_01 but not rss_zhihu_archive.
+pub(crate) fn is_dated_name(name:&str,table_name:&str,append_date:&str)->bool{
+    if name == table_name {
+        return true;
+    }
+    let date = match name.strip_prefix(format!("{}_",table_name).as_str()) {
+        Some(d) if !append_date.is_empty() => d,
+        _ => return false
+    };
+
+    // Digits and letters of a formatted date vary in length ( %-d, %B ), the rest is literal.
+    let sample = chrono::Local::now().format(append_date).to_string();
+    let mut pattern = String::from("^");
+    let mut last = ' ';
+    for c in sample.chars() {
+        let class = if c.is_ascii_digit() { 'd' } else if c.is_ascii_alphabetic() { 'a' } else { ' ' };
+        match class {
+            'd' if last != 'd' => pattern.push_str(r"\d+"),
+            'a' if last != 'a' => pattern.push_str("[A-Za-z]+"),
+            ' ' => pattern.push_str(regex::escape(c.to_string().as_str()).as_str()),
+            _ => {}
+        }
+        last = class;
+    }
+    pattern.push('$');
+    regex::Regex::new(pattern.as_str()).map(|r| r.is_match(date)).unwrap_or(false)
+}
+
+
+// A file or key holds the whole last batch, items skipped by `fetch_content` keep the content stored with them.
+pub(crate) fn keep_content(items:&[Item],stored:&str)->Vec<Item>{
+    let stored:std::collections::HashMap<String,String> = crate::item::from_json_string(stored).into_iter()
+        .filter(|i| !i.content.is_empty())
+        .map(|i| (i.uid(),i.content))
+        .collect();
+    items.iter()
+        .map(|i| {
+            let mut i = i.clone();
+            if i.content.is_empty() {
+                if let Some(content) = stored.get(i.uid().as_str()) {
+                    i.content = content.clone();
+                }
+            }
+            i
+        })
+        .collect()
+}
+
+
+// Retries a failed save of the wrapped sink before giving up.
+pub struct RetrySink{
+    sink: Box<dyn Sink>,
+    retries: u32,
+    delay: std::time::Duration,
+}
+
+impl RetrySink{
+    pub fn new(sink:Box<dyn Sink>,retries:u32,delay:std::time::Duration)->Self{
+        Self{ sink, retries, delay }
+    }
+
+
+    // Result of `action` on the wrapped sink and the attempts it took, the last error once the retries run out.
+    fn retry<T>(&mut self,action:&str,mut f:impl FnMut(&mut dyn Sink)->Result<T,std::io::Error>)->Result<(T,u32),std::io::Error>{
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match f(self.sink.as_mut()) {
+                Ok(ret) => return Ok((ret,attempts)),
+                Err(e) => {
+                    if attempts > self.retries {
+                        return Err(std::io::Error::new(e.kind(),format!("{} ( after {} attempts )",e,attempts)));
+                    }
+                    tracing::warn!(attempt = attempts,attempts = self.retries + 1,"Failed by {} {}: {}",action,self.name(),e);
+                    std::thread::sleep(self.delay);
+                }
+            }
+        }
+    }
+}
+
+impl Sink for RetrySink{
+    fn name(&self)->String{
+        self.sink.name()
+    }
+
+    fn save(&mut self,items:&[Item])->Result<SinkReport,std::io::Error>{
+        let (mut report,attempts) = self.retry("save",|sink| sink.save(items))?;
+        report.attempts = attempts;
+        Ok(report)
+    }
+
+    fn contains(&mut self,uid:&str)->Result<bool,std::io::Error>{
+        Ok(self.retry("check stored",|sink| sink.contains(uid))?.0)
+    }
+
+    fn find(&mut self,uid:&str)->Result<Option<Item>,std::io::Error>{
+        self.sink.find(uid)
+    }
+
+    fn export(&mut self)->Result<Vec<Item>,std::io::Error>{
+        self.sink.export()
+    }
+
+    fn accepts(&self,item:&Item)->bool{
+        self.sink.accepts(item)
+    }
+}
+
+
+// Passes only the items carrying one of `tags` to the wrapped sink.
+pub struct RouteSink{
+    sink: Box<dyn Sink>,
+    tags: Vec<String>,
+}
+
+impl RouteSink{
+    pub fn new(sink:Box<dyn Sink>,tags:&[String])->Self{
+        Self{ sink, tags: tags.to_vec() }
+    }
+}
+
+impl Sink for RouteSink{
+    fn name(&self)->String{
+        self.sink.name()
+    }
+
+    fn save(&mut self,items:&[Item])->Result<SinkReport,std::io::Error>{
+        let routed:Vec<Item> = items.iter()
+            .filter(|item| self.accepts(item))
+            .cloned()
+            .collect();
+        self.sink.save(routed.as_slice())
+    }
+
+    fn contains(&mut self,uid:&str)->Result<bool,std::io::Error>{
+        self.sink.contains(uid)
+    }
+
+    fn find(&mut self,uid:&str)->Result<Option<Item>,std::io::Error>{
+        self.sink.find(uid)
+    }
+
+    fn export(&mut self)->Result<Vec<Item>,std::io::Error>{
+        self.sink.export()
+    }
+
+    fn accepts(&self,item:&Item)->bool{
+        item.tags.iter().any(|t| self.tags.contains(t)) && self.sink.accepts(item)
+    }
+}
+
+
+pub fn from_output(output:&OutputTarget,now:&DateTime<Local>)->Box<dyn Sink>{
+    let mut sink:Box<dyn Sink> = match output.save_type {
+        SaveType::File => Box::new(FileSink::new(output.table_name.as_str(),output.append_date.as_str(),now)),
+        SaveType::Redis => Box::new(RedisSink::new(output.driver_url.as_str(),output.table_name.as_str(),output.append_date.as_str(),now)),
+        SaveType::MySQL => Box::new(MySqlSink::new(output.driver_url.as_str(),output.table_name.as_str(),output.append_date.as_str(),now)),
+        SaveType::None => Box::new(StdoutSink::new()),
+    };
+
+    if !output.tags.is_empty() {
+        sink = Box::new(RouteSink::new(sink,output.tags.as_slice()));
+    }
+
+    if output.retries > 0 {
+        return Box::new(RetrySink::new(sink,output.retries,std::time::Duration::from_secs(output.retry_delay)));
+    }
+    sink
+}
+
+
+pub fn from_config(conf:&CliConfig,now:&DateTime<Local>)->Vec<Box<dyn Sink>>{
+    conf.outputs().iter()
+        .map(|output| from_output(output,now))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::MemorySink;
+
+    #[test]
+    fn retry_stored_check(){
+        let memory = MemorySink{ failures: 2, ..Default::default() };
+        let checks = memory.checks.clone();
+        let mut sink = RetrySink::new(Box::new(memory),2,std::time::Duration::from_millis(0));
+        assert!(!sink.contains("a").unwrap());
+        assert_eq!(checks.get(),3);
+
+        let memory = MemorySink{ failures: 2, ..Default::default() };
+        let mut sink = RetrySink::new(Box::new(memory),1,std::time::Duration::from_millis(0));
+        assert!(sink.contains("a").unwrap_err().to_string().contains("after 2 attempts"));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::item::{self, Item};
+use crate::sink::{self, Sink, SinkReport};
+use chrono::{DateTime, Local};
+use std::io::Write;
+
+
+pub struct FileSink{
+    filename: String,
+    table_name: String,
+    append_date: String,
+}
+
+impl FileSink{
+    pub fn new(table_name:&str,append_date:&str,now:&DateTime<Local>)->Self{
+        let mut filename = table_name.to_string();
+        if !append_date.is_empty() {
+            filename = filename.replace(".json","");
+            filename = format!("{}_{}",filename,now.format(append_date));
+        }
+
+        if !filename.contains(".json") {
+            filename.push_str(".json");
+        };
+
+        Self{
+            filename,
+            table_name: table_name.to_string(),
+            append_date: append_date.to_string(),
+        }
+    }
+}
+
+impl FileSink{
+    // Every file written for this table, dated or not.
+    fn files(&self)->Result<Vec<std::path::PathBuf>,std::io::Error>{
+        let path = std::path::Path::new(self.table_name.as_str());
+        let stem = path.file_name()
+            .map(|n| n.to_string_lossy().replace(".json",""))
+            .unwrap_or_default();
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => std::path::PathBuf::from(".")
+        };
+
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let file = entry?.path();
+            let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let dated = name.strip_suffix(".json")
+                .map(|n| sink::is_dated_name(n,stem.as_str(),self.append_date.as_str()))
+                .unwrap_or(false);
+            if dated {
+                files.push(file);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl Sink for FileSink{
+    fn name(&self)->String{
+        String::from("file")
+    }
+
+    fn save(&mut self,items:&[Item])->Result<SinkReport,std::io::Error>{
+        let data = match std::fs::read_to_string(self.filename.as_str()) {
+            Ok(stored) => item::to_json_string(sink::keep_content(items,stored.as_str()).as_slice()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => item::to_json_string(items),
+            Err(e) => return Err(e)
+        };
+        let fd = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.filename.as_str())?;
+        let mut writer = std::io::BufWriter::new(fd);
+        writer.write_all(data.as_bytes())?;
+        writer.flush()?;
+
+        Ok(SinkReport{
+            sink: self.name(),
+            target: self.filename.clone(),
+            saved: items.len(),
+            uids: items.iter().map(|i| i.uid()).collect(),
+            ..Default::default()
+        })
+    }
+
+    // Only the file of the day counts, like the table of the day for MySQL.
+    fn contains(&mut self,uid:&str)->Result<bool,std::io::Error>{
+        match std::fs::read_to_string(self.filename.as_str()) {
+            Ok(data) => Ok(item::find_json(data.as_str(),uid).is_some()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn find(&mut self,uid:&str)->Result<Option<Item>,std::io::Error>{
+        for file in self.files()? {
+            let data = std::fs::read_to_string(file)?;
+            if let Some(found) = item::find_json(data.as_str(),uid) {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+
+    fn export(&mut self)->Result<Vec<Item>,std::io::Error>{
+        let mut items = Vec::new();
+        for file in self.files()? {
+            let data = std::fs::read_to_string(file)?;
+            items.extend(item::from_json_string(data.as_str()));
+        }
+        Ok(items)
+    }
+}
@@ -0,0 +1,356 @@
+use crate::item::{Enclosure, Item};
+use crate::sink::{self, Sink, SinkReport};
+use ::mysql::prelude::*;
+use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
+
+
+pub struct MySqlSink{
+    address: String,
+    table_name: String,
+    append_date: String,
+    table: String,
+    create_time: String,
+    pool: Option<::mysql::Pool>,
+}
+
+impl MySqlSink{
+    pub fn new(address:&str,table_name:&str,append_date:&str,now:&DateTime<Local>)->Self{
+        Self{
+            address: address.to_string(),
+            table_name: table_name.to_string(),
+            append_date: append_date.to_string(),
+            table: sink::dated_name(table_name,append_date,now),
+            create_time: now.timestamp().to_string(),
+            pool: None,
+        }
+    }
+
+
+    fn connect(&mut self)->Result<::mysql::PooledConn,std::io::Error>{
+        if self.pool.is_none() {
+            self.pool = match ::mysql::Pool::new(self.address.as_str()) {
+                Ok(con) => Some(con),
+                Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
+            };
+        }
+
+        match self.pool.as_ref().unwrap().get_conn() {
+            Ok(c) => Ok(c),
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
+        }
+    }
+
+
+    // Columns of every table written for this feed, dated or not, child tables included.
+    // Read only: tables that predate a column are migrated by the next save, not here.
+    fn schema(&self,connect:&mut ::mysql::PooledConn)->Result<BTreeMap<String,Vec<String>>,std::io::Error>{
+        let pattern = format!("{}%",self.table_name.replace('\\',"\\\\").replace('_',"\\_").replace('%',"\\%"));
+        let columns = connect.exec::<(String,String),_,_>(
+            "SELECT `table_name`,`column_name` FROM `information_schema`.`columns` WHERE `table_schema` = DATABASE() AND `table_name` LIKE ? ORDER BY `table_name`,`ordinal_position`",
+            (pattern,)
+        );
+
+        match columns {
+            Ok(columns) => {
+                let mut schema:BTreeMap<String,Vec<String>> = BTreeMap::new();
+                for (table_name,column) in columns {
+                    schema.entry(table_name).or_default().push(column);
+                }
+                Ok(schema)
+            }
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
+        }
+    }
+
+    // Item tables of the feed itself, `feed` or `feed_2020_01_01` but never `feed_archive`.
+    fn tables(&self,schema:&BTreeMap<String,Vec<String>>)->Vec<String>{
+        schema.keys()
+            .filter(|t| sink::is_dated_name(t.as_str(),self.table_name.as_str(),self.append_date.as_str()))
+            .filter(|t| !CHILD_TABLES.iter().any(|(suffix,_)| t.ends_with(format!("_{}",suffix).as_str())))
+            .cloned()
+            .collect()
+    }
+}
+
+
+// Columns added after the first release, created on tables that predate them.
+const COLUMNS:[(&str,&str);9] = [
+    ("content","LONGTEXT NOT NULL COLLATE 'utf8mb4_unicode_ci' AFTER `description`"),
+    ("tags","varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci' AFTER `publish`"),
+    ("category","varchar(64) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci' AFTER `tags`"),
+    ("confidence","double NOT NULL DEFAULT 0 AFTER `category`"),
+    ("fingerprint","char(16) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci' AFTER `confidence`"),
+    ("cluster_id","varchar(64) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci' AFTER `fingerprint`"),
+    ("description_html","LONGTEXT NOT NULL COLLATE 'utf8mb4_unicode_ci' AFTER `description`"),
+    ("description_text","LONGTEXT NOT NULL COLLATE 'utf8mb4_unicode_ci' AFTER `description_html`"),
+    ("comments","varchar(2048) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci' AFTER `content`"),
+];
+
+// Lists of an item live in `{table}_{suffix}`, keyed by the item uid.
+const CHILD_TABLES:[(&str,&str);3] = [
+    ("categories",r#"`uid` varchar(64) NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                `position` int unsigned NOT NULL,
+                `category` varchar(255) NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                PRIMARY KEY (`uid`,`position`)"#),
+    ("enclosures",r#"`uid` varchar(64) NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                `position` int unsigned NOT NULL,
+                `url` varchar(2048) NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                `type` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `length` bigint unsigned NOT NULL DEFAULT 0,
+                PRIMARY KEY (`uid`,`position`)"#),
+    ("thumbnails",r#"`uid` varchar(64) NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                `position` int unsigned NOT NULL,
+                `url` varchar(2048) NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                PRIMARY KEY (`uid`,`position`)"#),
+];
+
+// The uid column was char(32) for md5, other hashes need up to 64 characters.
+fn migrate<Q:Queryable>(connect:&mut Q,table_name:&str)->Result<(),::mysql::Error>{
+    let found = connect.exec::<(String,Option<u64>),_,_>(
+        "SELECT `column_name`,`character_maximum_length` FROM `information_schema`.`columns` WHERE `table_schema` = DATABASE() AND `table_name` = ?",
+        (table_name.to_string(),)
+    )?;
+    for (column,definition) in COLUMNS.iter() {
+        if !found.iter().any(|(c,_)| c == column) {
+            connect.query_drop(format!("ALTER TABLE `{}` ADD COLUMN `{}` {}",table_name,column,definition))?;
+        }
+    }
+    for (suffix,columns) in CHILD_TABLES.iter() {
+        connect.query_drop(format!(
+            "CREATE TABLE IF NOT EXISTS `{}_{}` ({})COLLATE=utf8mb4_unicode_ci ENGINE=InnoDB CHARSET=utf8mb4",
+            table_name,suffix,columns
+        ))?;
+    }
+    if found.iter().any(|(c,length)| c == "uid" && length.unwrap_or(0) < 64) {
+        connect.query_drop(format!(
+            "ALTER TABLE `{}` MODIFY `uid` varchar(64) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci'",
+            table_name
+        ))?;
+    }
+    Ok(())
+}
+
+const ITEM_COLUMNS:[&str;16] = [
+    "uid","title","link","author","description","content","guid","publish","tags","category",
+    "confidence","fingerprint","cluster_id","description_html","description_text","comments"
+];
+
+// Columns a table does not have yet are read as empty.
+fn select_item(columns:&[String])->String{
+    let fields:Vec<String> = ITEM_COLUMNS.iter()
+        .map(|c| if columns.iter().any(|f| f == c) { format!("`{}`",c) } else { format!("'' AS `{}`",c) })
+        .collect();
+    format!("SELECT {}",fields.join(","))
+}
+
+fn column<T: ::mysql::prelude::FromValue>(row:&mut ::mysql::Row,index:usize)->Option<T>{
+    row.take_opt::<T,_>(index).and_then(|v| v.ok())
+}
+
+fn from_row(mut row: ::mysql::Row)->Item{
+    let mut text = |index:usize| column::<String>(&mut row,index).unwrap_or_default();
+    let (uid,title,link,author,description,content,guid,publish,tags,category,fingerprint,cluster_id) = (
+        text(0),text(1),text(2),text(3),text(4),text(5),text(6),text(7),text(8),text(9),text(11),text(12)
+    );
+    let (description_html,description_text,comments) = (text(13),text(14),text(15));
+    Item{
+        uid, title, link, author, description, description_html, description_text, guid, publish, content, comments,
+        tags: tags.split(',').filter(|t| !t.is_empty()).map(|t| t.to_string()).collect(),
+        category,
+        confidence: column::<f64>(&mut row,10).unwrap_or(0.0),
+        fingerprint,
+        cluster_id,
+        ..Default::default()
+    }
+}
+
+fn save_children<Q:Queryable>(connect:&mut Q,table_name:&str,item:&Item,uid:&str)->Result<(),::mysql::Error>{
+    let uid = uid.to_string();
+    connect.exec_batch(
+        format!("INSERT INTO `{}_categories` (`uid`,`position`,`category`) VALUES( ? , ? , ? )",table_name),
+        item.categories.iter().enumerate().map(|(i,c)| (uid.clone(),i as u32,c.clone()))
+    )?;
+    connect.exec_batch(
+        format!("INSERT INTO `{}_enclosures` (`uid`,`position`,`url`,`type`,`length`) VALUES( ? , ? , ? , ? , ? )",table_name),
+        item.enclosures.iter().enumerate().map(|(i,e)| (uid.clone(),i as u32,e.url.clone(),e.mime_type.clone(),e.length))
+    )?;
+    connect.exec_batch(
+        format!("INSERT INTO `{}_thumbnails` (`uid`,`position`,`url`) VALUES( ? , ? , ? )",table_name),
+        item.thumbnails.iter().enumerate().map(|(i,t)| (uid.clone(),i as u32,t.clone()))
+    )
+}
+
+// Child tables missing on a table not migrated yet leave the lists empty.
+fn load_children<Q:Queryable>(connect:&mut Q,table_name:&str,schema:&BTreeMap<String,Vec<String>>,item:&mut Item)->Result<(),::mysql::Error>{
+    let uid = item.uid.clone();
+    let exists = |suffix:&str| schema.contains_key(format!("{}_{}",table_name,suffix).as_str());
+    if exists("categories") {
+        item.categories = connect.exec(
+            format!("SELECT `category` FROM `{}_categories` WHERE `uid` = ? ORDER BY `position`",table_name),
+            (uid.clone(),)
+        )?;
+    }
+    if exists("enclosures") {
+        item.enclosures = connect.exec_map(
+            format!("SELECT `url`,`type`,`length` FROM `{}_enclosures` WHERE `uid` = ? ORDER BY `position`",table_name),
+            (uid.clone(),),
+            |(url,mime_type,length)| Enclosure{ url, mime_type, length }
+        )?;
+    }
+    if exists("thumbnails") {
+        item.thumbnails = connect.exec(
+            format!("SELECT `url` FROM `{}_thumbnails` WHERE `uid` = ? ORDER BY `position`",table_name),
+            (uid,)
+        )?;
+    }
+    Ok(())
+}
+
+
+impl Sink for MySqlSink{
+    fn name(&self)->String{
+        String::from("mysql")
+    }
+
+    fn save(&mut self,items:&[Item])->Result<SinkReport,std::io::Error>{
+        let table_name = self.table.clone();
+        let create_time = self.create_time.clone();
+        let mut connect = self.connect()?;
+
+        let mut transaction = match connect.start_transaction(::mysql::TxOpts::default()) {
+            Ok(c) => c,
+            Err(e) =>return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
+        };
+
+        let create_sql = format!(r#"CREATE TABLE IF NOT EXISTS `{}` (
+                `uid` varchar(64) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `title` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `link` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `author` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `description` LONGTEXT NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                `description_html` LONGTEXT NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                `description_text` LONGTEXT NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                `content` LONGTEXT NOT NULL COLLATE 'utf8mb4_unicode_ci',
+                `comments` varchar(2048) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `guid` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `publish` varchar(50) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `tags` varchar(255) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `category` varchar(64) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `confidence` double NOT NULL DEFAULT 0,
+                `fingerprint` char(16) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `cluster_id` varchar(64) NOT NULL DEFAULT '' COLLATE 'utf8mb4_unicode_ci',
+                `create_time` int unsigned NOT NULL,
+                PRIMARY KEY (`uid`)
+            )COLLATE=utf8mb4_unicode_ci ENGINE=InnoDB CHARSET=utf8mb4"#,
+            table_name
+        );
+
+        if let Err(e) = transaction.query_drop(create_sql).and_then(|_| migrate(&mut transaction,table_name.as_str())) {
+            transaction.rollback().unwrap();
+            return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()));
+        }
+
+        let query_sql = format!("SELECT `uid` FROM `{}` WHERE `uid` = ?",table_name);
+        let insert_sql = format!(
+            r"INSERT INTO `{}` (`uid`,`title`,`link`,`author`,`description`,`description_html`,`description_text`,`content`,`comments`,`guid`,`publish`,`tags`,`category`,`confidence`,`fingerprint`,`cluster_id`,`create_time`) VALUES( ? , ? , ? , ? , ? , ? , ? , ? , ? , ? , ? , ? , ? , ? , ? , ? , ? )",
+            table_name
+        );
+
+        let mut uids = Vec::new();
+        let mut failed = 0;
+        for val in items.iter() {
+            let uid = val.uid();
+
+            let exists = transaction.exec_first::<String, _, _>(query_sql.as_str(), (uid.clone(),));
+            if let Ok(Some(_)) = exists {
+                continue;
+            }
+
+            let params:Vec<::mysql::Value> = vec![
+                uid.clone().into(),val.title.clone().into(),val.link.clone().into(),val.author.clone().into(),
+                val.description.clone().into(),val.description_html.clone().into(),val.description_text.clone().into(),
+                val.content.clone().into(),val.comments.clone().into(),val.guid.clone().into(),val.publish.clone().into(),
+                val.tags.join(",").into(),val.category.clone().into(),val.confidence.into(),
+                val.fingerprint.clone().into(),val.cluster_id.clone().into(),create_time.clone().into()
+            ];
+            let inserted = transaction.exec_drop(insert_sql.as_str(),params)
+                .and_then(|_| save_children(&mut transaction,table_name.as_str(),val,uid.as_str()));
+            match inserted {
+                Ok(_) => {
+                    tracing::info!(uid = %uid,table = %table_name,"inserted");
+                    uids.push(uid);
+                }
+                Err(e) => {
+                    tracing::warn!(uid = %uid,table = %table_name,"Failed by insert: {}",e);
+                    failed += 1;
+                }
+            }
+        }
+
+        if let Err(e) = transaction.commit() {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()));
+        }
+
+        Ok(SinkReport{
+            sink: self.name(),
+            target: table_name,
+            saved: uids.len(),
+            uids,
+            failed,
+            ..Default::default()
+        })
+    }
+
+    // A missing table of the day means nothing is stored yet.
+    fn contains(&mut self,uid:&str)->Result<bool,std::io::Error>{
+        let query_sql = format!("SELECT `uid` FROM `{}` WHERE `uid` = ?",self.table);
+        let mut connect = self.connect()?;
+        match connect.exec_first::<String, _, _>(query_sql.as_str(), (uid.to_string(),)) {
+            Ok(found) => Ok(found.is_some()),
+            // ER_NO_SUCH_TABLE
+            Err(::mysql::Error::MySqlError(e)) if e.code == 1146 => Ok(false),
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
+        }
+    }
+
+    fn find(&mut self,uid:&str)->Result<Option<Item>,std::io::Error>{
+        let mut connect = self.connect()?;
+        let schema = self.schema(&mut connect)?;
+        for table_name in self.tables(&schema) {
+            let query_sql = format!("{} FROM `{}` WHERE `uid` = ?",select_item(&schema[&table_name]),table_name);
+            let row = match connect.exec_first::<::mysql::Row,_,_>(query_sql,(uid.to_string(),)) {
+                Ok(row) => row,
+                Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
+            };
+            if let Some(row) = row {
+                let mut item = from_row(row);
+                if let Err(e) = load_children(&mut connect,table_name.as_str(),&schema,&mut item) {
+                    return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()));
+                }
+                return Ok(Some(item));
+            }
+        }
+        Ok(None)
+    }
+
+    fn export(&mut self)->Result<Vec<Item>,std::io::Error>{
+        let mut connect = self.connect()?;
+        let mut items = Vec::new();
+        let schema = self.schema(&mut connect)?;
+        for table_name in self.tables(&schema) {
+            let query_sql = format!("{} FROM `{}` ORDER BY `create_time`",select_item(&schema[&table_name]),table_name);
+            match connect.query::<::mysql::Row,_>(query_sql) {
+                Ok(rows) => for row in rows {
+                    let mut item = from_row(row);
+                    if let Err(e) = load_children(&mut connect,table_name.as_str(),&schema,&mut item) {
+                        return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()));
+                    }
+                    items.push(item);
+                },
+                Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::NotConnected,e.to_string()))
+            }
+        }
+        Ok(items)
+    }
+}
@@ -0,0 +1,129 @@
+use crate::item::{self, Item};
+use crate::sink::{self, Sink, SinkReport};
+use ::redis::Commands;
+use chrono::{DateTime, Local};
+
+
+pub struct RedisSink{
+    address: String,
+    table_name: String,
+    append_date: String,
+    key: String,
+}
+
+impl RedisSink{
+    pub fn new(address:&str,table_name:&str,append_date:&str,now:&DateTime<Local>)->Self{
+        Self{
+            address: address.to_string(),
+            table_name: table_name.to_string(),
+            append_date: append_date.to_string(),
+            key: sink::dated_name(table_name,append_date,now),
+        }
+    }
+
+
+    fn connect(&self)->Result<::redis::Connection,std::io::Error>{
+        let redis_client = match ::redis::Client::open(self.address.clone()) {
+            Ok(con) => con,
+            Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
+        };
+
+        match redis_client.get_connection(){
+            Ok(con) => Ok(con),
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
+        }
+    }
+
+
+    // Every key written for this table, dated or not. SCAN does not block the server like KEYS.
+    fn keys(&self,connect:&mut ::redis::Connection)->Result<Vec<String>,std::io::Error>{
+        let mut pattern = String::new();
+        for c in self.table_name.chars() {
+            if matches!(c,'*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('*');
+
+        let scanned = connect.scan_match::<String,String>(pattern)
+            .and_then(|keys| keys.collect::<::redis::RedisResult<Vec<String>>>());
+        match scanned {
+            Ok(keys) => {
+                let mut k:Vec<String> = keys.into_iter()
+                    .filter(|k| sink::is_dated_name(k.as_str(),self.table_name.as_str(),self.append_date.as_str()))
+                    .collect();
+                k.sort();
+                k.dedup();
+                Ok(k)
+            }
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
+        }
+    }
+
+    // The batch stored under the key of the day, if any.
+    fn stored(&self,connect:&mut ::redis::Connection)->Result<Option<String>,std::io::Error>{
+        match connect.get::<String,Option<String>>(self.key.clone()) {
+            Ok(data) => Ok(data),
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
+        }
+    }
+}
+
+impl Sink for RedisSink{
+    fn name(&self)->String{
+        String::from("redis")
+    }
+
+    fn save(&mut self,items:&[Item])->Result<SinkReport,std::io::Error>{
+        let mut connect = self.connect()?;
+        let data = match self.stored(&mut connect)? {
+            Some(stored) => item::to_json_string(sink::keep_content(items,stored.as_str()).as_slice()),
+            None => item::to_json_string(items)
+        };
+
+        if let Err(e) = connect.set::<String,&[u8],String>(self.key.clone(),data.as_bytes()) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,e.to_string()))
+        };
+
+        Ok(SinkReport{
+            sink: self.name(),
+            target: self.key.clone(),
+            saved: items.len(),
+            uids: items.iter().map(|i| i.uid()).collect(),
+            ..Default::default()
+        })
+    }
+
+    // Only the key of the day counts, like the table of the day for MySQL.
+    fn contains(&mut self,uid:&str)->Result<bool,std::io::Error>{
+        let mut connect = self.connect()?;
+        match self.stored(&mut connect)? {
+            Some(data) => Ok(item::find_json(data.as_str(),uid).is_some()),
+            None => Ok(false)
+        }
+    }
+
+    fn find(&mut self,uid:&str)->Result<Option<Item>,std::io::Error>{
+        let mut connect = self.connect()?;
+        for key in self.keys(&mut connect)? {
+            if let Ok(data) = connect.get::<String,String>(key) {
+                if let Some(found) = item::find_json(data.as_str(),uid) {
+                    return Ok(Some(found));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn export(&mut self)->Result<Vec<Item>,std::io::Error>{
+        let mut connect = self.connect()?;
+        let mut items = Vec::new();
+        for key in self.keys(&mut connect)? {
+            if let Ok(data) = connect.get::<String,String>(key) {
+                items.extend(item::from_json_string(data.as_str()));
+            }
+        }
+        Ok(items)
+    }
+}
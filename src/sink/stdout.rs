@@ -0,0 +1,35 @@
+use crate::item::{self, Item};
+use crate::sink::{Sink, SinkReport};
+use std::io::Write;
+
+
+#[derive(Default)]
+pub struct StdoutSink{}
+
+impl StdoutSink{
+    pub fn new()->Self{
+        Self{}
+    }
+}
+
+impl Sink for StdoutSink{
+    fn name(&self)->String{
+        String::from("stdout")
+    }
+
+    fn save(&mut self,items:&[Item])->Result<SinkReport,std::io::Error>{
+        let data = item::to_json_string(items);
+        let stdout = std::io::stdout();
+        let mut writer = std::io::BufWriter::new(stdout);
+        writer.write_all(data.as_bytes())?;
+        writer.flush()?;
+
+        Ok(SinkReport{
+            sink: self.name(),
+            target: String::from("-"),
+            saved: items.len(),
+            uids: items.iter().map(|i| i.uid()).collect(),
+            ..Default::default()
+        })
+    }
+}
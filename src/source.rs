@@ -0,0 +1,123 @@
+use crate::config::{CliConfig, SourceType};
+use crate::extras;
+use crate::fetch::Pages;
+use crate::item::Item;
+use crate::scrape;
+use easy_rss::RssParser;
+
+
+// Where items come from: fetch the remote document and parse it into items.
+pub trait Source{
+    fn fetch(&mut self)->Result<Vec<Item>,std::io::Error>;
+
+    // Charset the last fetch was decoded with, empty when unknown.
+    fn charset(&self)->String{
+        String::new()
+    }
+
+    // HTTP status of the last fetch, 0 when unknown.
+    fn status(&self)->u16{
+        0
+    }
+}
+
+
+pub struct RssSource{
+    conf: CliConfig,
+    parser: RssParser,
+    pages: Pages,
+}
+
+impl RssSource{
+    pub fn from(conf:&CliConfig)->Self{
+        Self{
+            conf: conf.clone(),
+            parser: RssParser::new(),
+            pages: Pages::new(conf.charset.as_str(),conf.chrome_path.as_str(),&conf.http),
+        }
+    }
+
+
+    // Requested by our own client instead of `RssParser::request_xml`, so the `http` options apply.
+    fn request_xml(&mut self)->Result<String,std::io::Error>{
+        self.pages.get_xml(self.conf.url.as_str(),self.conf.fetcher.as_str())
+    }
+}
+
+impl Source for RssSource{
+    fn fetch(&mut self)->Result<Vec<Item>,std::io::Error>{
+        self.parser.publish_tag = self.conf.publish_tag.clone();
+        self.parser.title_tag = self.conf.title_tag.clone();
+        self.parser.link_tag = self.conf.link_tag.clone();
+        self.parser.author_tag = self.conf.author_tag.clone();
+        self.parser.description_tag = self.conf.description_tag.clone();
+        self.parser.guid_tag = self.conf.guid_tag.clone();
+        self.parser.publish_tag = self.conf.publish_tag.clone();
+
+        let xml = self.request_xml()?;
+        self.parser.set_xml(xml.clone());
+
+        if !self.parser.check_xml() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,"Failed by check xml."));
+        }
+
+        let data = self.parser.parse_vec()?;
+        let mut items:Vec<Item> = data.into_iter().map(|val|Item{
+            title: val.title.clone(),
+            link: val.link.clone(),
+            author: val.author.clone(),
+            description: val.description.clone(),
+            guid: val.guid.clone(),
+            publish: val.publish.clone(),
+            ..Default::default()
+        }).collect();
+        extras::apply(items.as_mut_slice(),xml.as_str());
+        Ok(items)
+    }
+
+    fn charset(&self)->String{
+        self.pages.charset().to_string()
+    }
+
+    fn status(&self)->u16{
+        self.pages.status()
+    }
+}
+
+
+pub struct ScrapeSource{
+    conf: CliConfig,
+    pages: Pages,
+}
+
+impl ScrapeSource{
+    pub fn from(conf:&CliConfig)->Self{
+        Self{
+            conf: conf.clone(),
+            pages: Pages::new(conf.charset.as_str(),conf.chrome_path.as_str(),&conf.http),
+        }
+    }
+}
+
+impl Source for ScrapeSource{
+    fn fetch(&mut self)->Result<Vec<Item>,std::io::Error>{
+        let html = self.pages.get_html(self.conf.url.as_str(),self.conf.fetcher.as_str())?;
+        scrape::scrape(html.as_str(),self.conf.url.as_str(),&self.conf.scrape)
+    }
+
+    fn charset(&self)->String{
+        self.pages.charset().to_string()
+    }
+
+    fn status(&self)->u16{
+        self.pages.status()
+    }
+}
+
+
+pub fn from_config(conf:&CliConfig)->Box<dyn Source>{
+    match conf.source_type {
+        SourceType::Scrape => Box::new(ScrapeSource::from(conf)),
+        SourceType::RSS => Box::new(RssSource::from(conf)),
+    }
+}
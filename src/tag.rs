@@ -0,0 +1,88 @@
+use crate::config::FilterRule;
+use crate::filter::Rule;
+use crate::item::Item;
+use std::collections::BTreeMap;
+
+
+// An item gets every tag that has one matching rule.
+#[derive(Debug,Clone,Default)]
+pub struct Tagger{
+    tags: Vec<(String,Vec<Rule>)>,
+}
+
+impl Tagger{
+
+    // Err carries the key path of the broken rule, e.g. `tech[0].regex`.
+    pub fn from(conf:&BTreeMap<String,Vec<FilterRule>>)->Result<Self,(String,String)>{
+        let mut tags = Vec::new();
+        for (tag,rules) in conf.iter() {
+            let mut list = Vec::new();
+            for (i,rule) in rules.iter().enumerate() {
+                list.push(Rule::from(rule).map_err(|e| (format!("{}[{}].regex",tag,i),e))?);
+            }
+            tags.push((tag.clone(),list));
+        }
+        Ok(Self{ tags })
+    }
+
+
+    pub fn tags(&self,item:&Item)->Vec<String>{
+        self.tags.iter()
+            .filter(|(_,rules)| rules.iter().any(|r| r.check(item)))
+            .map(|(tag,_)| tag.clone())
+            .collect()
+    }
+
+
+    // Tags already set by the source are kept.
+    pub fn apply(&self,items:&mut [Item]){
+        for item in items.iter_mut() {
+            for tag in self.tags(item) {
+                if !item.tags.contains(&tag) {
+                    item.tags.push(tag);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::item;
+
+    #[test]
+    fn tags_with_one_matching_rule(){
+        let conf:BTreeMap<String,Vec<FilterRule>> = serde_json::from_str(r#"{
+            "rust":[{"keywords":["rust"]},{"domain":"rust-lang.org"}],
+            "release":[{"regex":"\\d+\\.\\d+","fields":["title"]}],
+            "empty":[]
+        }"#).unwrap();
+        let tagger = Tagger::from(&conf).unwrap();
+
+        let blog = Item{ link: String::from("https://blog.rust-lang.org/a"), ..item("Announcing 1.80","") };
+        assert_eq!(tagger.tags(&blog),vec!["release","rust"]);
+        let text = Item{ description_text: String::from("Written in Rust"), ..item("News","") };
+        assert_eq!(tagger.tags(&text),vec!["rust"]);
+        assert!(tagger.tags(&item("Weather","")).is_empty());
+    }
+
+    #[test]
+    fn apply_keeps_source_tags(){
+        let conf:BTreeMap<String,Vec<FilterRule>> = serde_json::from_str(r#"{"rust":[{"keywords":["rust"]}]}"#).unwrap();
+        let tagger = Tagger::from(&conf).unwrap();
+        let mut items = vec![
+            Item{ tags: vec![String::from("feed"),String::from("rust")], ..item("Rust","") },
+            Item{ tags: vec![String::from("feed")], ..item("Rust","") },
+            item("Go",""),
+        ];
+        tagger.apply(items.as_mut_slice());
+        assert_eq!(items[0].tags,vec!["feed","rust"]);
+        assert_eq!(items[1].tags,vec!["feed","rust"]);
+        assert!(items[2].tags.is_empty());
+
+        let broken:BTreeMap<String,Vec<FilterRule>> = serde_json::from_str(r#"{"bad":[{},{"regex":"("}]}"#).unwrap();
+        assert_eq!(Tagger::from(&broken).unwrap_err().0,"bad[1].regex");
+    }
+}
@@ -0,0 +1,85 @@
+// Fixtures shared by the unit tests of the modules.
+use crate::item::Item;
+use crate::sink::{Sink, SinkReport};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+
+pub fn item(title:&str,description:&str)->Item{
+    Item{
+        title: title.to_string(),
+        description: description.to_string(),
+        ..Default::default()
+    }
+}
+
+
+// An empty directory of the test, per process so parallel runs do not share it.
+pub fn temp_dir(name:&str)->std::path::PathBuf{
+    let dir = std::env::temp_dir().join(format!("easy-rss-{}-{}",name,std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+
+// Keeps the saved uids in memory, `contains` fails its first `failures` calls.
+#[derive(Default)]
+pub struct MemorySink{
+    pub uids: Rc<RefCell<Vec<String>>>,
+    pub checks: Rc<Cell<u32>>,
+    pub failures: u32,
+}
+
+impl Sink for MemorySink{
+    fn name(&self)->String{
+        String::from("memory")
+    }
+
+    fn save(&mut self,items:&[Item])->Result<SinkReport,std::io::Error>{
+        let uids:Vec<String> = items.iter().map(|item| item.uid()).collect();
+        self.uids.borrow_mut().extend(uids.iter().cloned());
+        Ok(SinkReport{
+            sink: self.name(),
+            saved: uids.len(),
+            uids,
+            ..Default::default()
+        })
+    }
+
+    fn contains(&mut self,uid:&str)->Result<bool,std::io::Error>{
+        self.checks.set(self.checks.get() + 1);
+        if self.checks.get() <= self.failures {
+            return Err(std::io::Error::other("sink down"));
+        }
+        Ok(self.uids.borrow().iter().any(|u| u == uid))
+    }
+}
+
+
+// Url of a local server answering one request with `body`.
+pub fn serve(content_type:&str,body:&[u8])->String{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/",listener.local_addr().unwrap());
+    let mut response = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",content_type,body.len()).into_bytes();
+    response.extend_from_slice(body);
+    std::thread::spawn(move ||{
+        let (mut stream,_) = listener.accept().unwrap();
+        let mut buf = [0u8;4096];
+        let _ = std::io::Read::read(&mut stream,&mut buf);
+        let _ = std::io::Write::write_all(&mut stream,response.as_slice());
+    });
+    url
+}
+
+
+// PNG of `width`x`height` pixels, `color` of each pixel by its position.
+pub fn png(width:u32,height:u32,color:impl Fn(u32,u32)->[u8;3])->Vec<u8>{
+    let img = image::RgbaImage::from_fn(width,height,|x,y|{
+        let [r,g,b] = color(x,y);
+        image::Rgba([r,g,b,255])
+    });
+    let mut data = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::ImageRgba8(img).write_to(&mut data,image::ImageFormat::Png).unwrap();
+    data.into_inner()
+}
@@ -0,0 +1,329 @@
+use crate::throw_err;
+use chrono::{DateTime, Utc};
+use sha1::{Digest, Sha1};
+use std::io::Write;
+
+
+#[derive(Debug,Clone)]
+pub struct WarcExchange{
+    pub url: String,
+    pub method: String,
+    pub request_headers: Vec<(String,String)>,
+    pub status: u32,
+    pub status_text: String,
+    pub response_headers: Vec<(String,String)>,
+    pub mime_type: String,
+    pub body: Vec<u8>,
+    pub date: DateTime<Utc>,
+}
+
+
+pub struct WarcWriter{
+    filename: String,
+    warc: std::io::BufWriter<std::fs::File>,
+    cdx_path: String,
+    cdx_lines: Vec<String>,
+    offset: u64,
+    serial: u64,
+}
+
+
+fn sha1_base32(data:&[u8])->String{
+    let digest = Sha1::digest(data);
+    data_encoding::BASE32.encode(digest.as_slice())
+}
+
+
+// SURT form used as the CDX sort key: "https://www.Example.com/a?b" => "com,example)/a?b"
+pub fn surt(url:&str)->String{
+    let parsed = match url::Url::parse(url) {
+        Ok(u) => u,
+        Err(_) => return url.to_lowercase()
+    };
+
+    let host = parsed.host_str().unwrap_or("").to_lowercase();
+    let host = host.trim_start_matches("www.");
+    let mut parts:Vec<&str> = host.split('.').collect();
+    parts.reverse();
+
+    let mut key = parts.join(",");
+    if let Some(port) = parsed.port() {
+        key.push_str(format!(":{}",port).as_str());
+    }
+    key.push(')');
+    key.push_str(parsed.path().to_lowercase().as_str());
+    if let Some(query) = parsed.query() {
+        key.push('?');
+        key.push_str(query.to_lowercase().as_str());
+    }
+    key
+}
+
+
+impl WarcWriter{
+
+    pub fn create(warc_path:&str,cdx_path:&str)->Result<Self,std::io::Error>{
+        let fd = std::fs::File::create(warc_path)?;
+        let filename = std::path::Path::new(warc_path)
+            .file_name()
+            .map(|f|f.to_string_lossy().to_string())
+            .unwrap_or_else(|| warc_path.to_string());
+
+        Ok(Self{
+            filename,
+            warc: std::io::BufWriter::new(fd),
+            cdx_path: cdx_path.to_string(),
+            cdx_lines: Vec::new(),
+            offset: 0,
+            serial: 0,
+        })
+    }
+
+
+    fn record_id(&mut self,seed:&str)->String{
+        self.serial += 1;
+        let hash = format!(
+            "{:x}",
+            md5::compute(format!("{}|{}|{}",self.filename,self.serial,seed).as_bytes())
+        );
+        format!(
+            "<urn:uuid:{}-{}-{}-{}-{}>",
+            &hash[0..8],&hash[8..12],&hash[12..16],&hash[16..20],&hash[20..32]
+        )
+    }
+
+
+    fn write_record(&mut self,headers:&[(&str,String)],block:&[u8])->Result<u64,std::io::Error>{
+        let mut head = String::from("WARC/1.0\r\n");
+        headers.iter().for_each(|(k,v)|{
+            head.push_str(format!("{}: {}\r\n",k,v).as_str());
+        });
+        head.push_str(format!("Content-Length: {}\r\n\r\n",block.len()).as_str());
+
+        self.warc.write_all(head.as_bytes())?;
+        self.warc.write_all(block)?;
+        self.warc.write_all(b"\r\n\r\n")?;
+
+        let length = (head.len() + block.len() + 4) as u64;
+        self.offset += length;
+        Ok(length)
+    }
+
+
+    pub fn write_info(&mut self,software:&str)->Result<(),std::io::Error>{
+        let block = format!(
+            "software: {}\r\nformat: WARC File Format 1.0\r\n",
+            software
+        );
+        let record_id = self.record_id("warcinfo");
+        let filename = self.filename.clone();
+
+        self.write_record(&[
+            ("WARC-Type","warcinfo".to_string()),
+            ("WARC-Record-ID",record_id),
+            ("WARC-Date",Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+            ("WARC-Filename",filename),
+            ("Content-Type","application/warc-fields".to_string()),
+        ],block.as_bytes())?;
+        Ok(())
+    }
+
+
+    pub fn write_exchange(&mut self,exchange:&WarcExchange)->Result<(),std::io::Error>{
+        let parsed = url::Url::parse(exchange.url.as_str())
+            .map_err(|e|throw_err(e.to_string().as_str()))?;
+        let warc_date = exchange.date.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+        // Response: the body handed over by the browser is already decoded,
+        // so transfer/content encodings and the original length no longer apply.
+        let mut response = format!("HTTP/1.1 {} {}\r\n",exchange.status,exchange.status_text);
+        exchange.response_headers.iter()
+            .filter(|(k,_)|{
+                let k = k.to_lowercase();
+                k != "content-encoding" && k != "transfer-encoding" && k != "content-length"
+            })
+            .for_each(|(k,v)|{
+                response.push_str(format!("{}: {}\r\n",k,v).as_str());
+            });
+        response.push_str(format!("Content-Length: {}\r\n\r\n",exchange.body.len()).as_str());
+
+        let mut response_block = response.into_bytes();
+        response_block.extend_from_slice(exchange.body.as_slice());
+
+        let payload_digest = sha1_base32(exchange.body.as_slice());
+        let response_id = self.record_id(exchange.url.as_str());
+        let response_offset = self.offset;
+        let response_length = self.write_record(&[
+            ("WARC-Type","response".to_string()),
+            ("WARC-Record-ID",response_id.clone()),
+            ("WARC-Date",warc_date.clone()),
+            ("WARC-Target-URI",exchange.url.clone()),
+            ("WARC-Payload-Digest",format!("sha1:{}",payload_digest)),
+            ("WARC-Block-Digest",format!("sha1:{}",sha1_base32(response_block.as_slice()))),
+            ("Content-Type","application/http; msgtype=response".to_string()),
+        ],response_block.as_slice())?;
+
+        // Request
+        let mut target = parsed.path().to_string();
+        if let Some(query) = parsed.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        let mut request = format!("{} {} HTTP/1.1\r\n",exchange.method,target);
+        if !exchange.request_headers.iter().any(|(k,_)|k.to_lowercase() == "host") {
+            request.push_str(format!("Host: {}\r\n",parsed.host_str().unwrap_or("")).as_str());
+        }
+        exchange.request_headers.iter()
+            .filter(|(k,_)|!k.starts_with(':'))
+            .for_each(|(k,v)|{
+                request.push_str(format!("{}: {}\r\n",k,v).as_str());
+            });
+        request.push_str("\r\n");
+
+        let request_id = self.record_id(format!("request {}",exchange.url).as_str());
+        self.write_record(&[
+            ("WARC-Type","request".to_string()),
+            ("WARC-Record-ID",request_id),
+            ("WARC-Date",warc_date),
+            ("WARC-Target-URI",exchange.url.clone()),
+            ("WARC-Concurrent-To",response_id),
+            ("WARC-Block-Digest",format!("sha1:{}",sha1_base32(request.as_bytes()))),
+            ("Content-Type","application/http; msgtype=request".to_string()),
+        ],request.as_bytes())?;
+
+        // CDX 11: N b a m s k r M S V g
+        let mime_type = if !exchange.mime_type.is_empty() {
+            exchange.mime_type.clone()
+        }else{
+            String::from("-")
+        };
+        let redirect = exchange.response_headers.iter()
+            .find(|(k,_)|k.to_lowercase() == "location")
+            .map(|(_,v)|v.replace(' ',"%20"))
+            .unwrap_or_else(|| String::from("-"));
+
+        self.cdx_lines.push(format!(
+            "{} {} {} {} {} {} {} - {} {} {}",
+            surt(exchange.url.as_str()),
+            exchange.date.format("%Y%m%d%H%M%S"),
+            exchange.url.replace(' ',"%20"),
+            mime_type,
+            exchange.status,
+            payload_digest,
+            redirect,
+            response_length,
+            response_offset,
+            self.filename
+        ));
+        Ok(())
+    }
+
+
+    pub fn finish(mut self)->Result<(),std::io::Error>{
+        self.warc.flush()?;
+
+        self.cdx_lines.sort();
+        let fd = std::fs::File::create(self.cdx_path.as_str())?;
+        let mut writer = std::io::BufWriter::new(fd);
+        writer.write_all(b" CDX N b a m s k r M S V g\n")?;
+        for line in self.cdx_lines.iter() {
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::testutil::temp_dir;
+
+    fn exchange(url:&str,status:u32,headers:&[(&str,&str)],body:&str)->WarcExchange{
+        WarcExchange{
+            url: url.to_string(),
+            method: String::from("GET"),
+            request_headers: vec![(String::from(":method"),String::from("GET")),(String::from("Accept"),String::from("*/*"))],
+            status,
+            status_text: String::from("OK"),
+            response_headers: headers.iter().map(|(k,v)| (k.to_string(),v.to_string())).collect(),
+            mime_type: String::from("text/html"),
+            body: body.as_bytes().to_vec(),
+            date: chrono::TimeZone::with_ymd_and_hms(&Utc,2020,1,2,3,4,5).unwrap(),
+        }
+    }
+
+    // Header block and payload of the record starting at `data`, checked against its Content-Length.
+    fn record(data:&[u8])->(String,Vec<u8>){
+        let end = data.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(data[..end].to_vec()).unwrap();
+        let length:usize = head.lines()
+            .find_map(|l| l.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let block = data[end+4..end+4+length].to_vec();
+        assert_eq!(&data[end+4+length..end+8+length],b"\r\n\r\n");
+        (head,block)
+    }
+
+    #[test]
+    fn surt_keys(){
+        assert_eq!(surt("https://www.Example.com/A?B=1"),"com,example)/a?b=1");
+        assert_eq!(surt("http://news.example.com:8080/"),"com,example,news:8080)/");
+        assert_eq!(surt("not a url"),"not a url");
+    }
+
+    #[test]
+    fn records_and_cdx_offsets(){
+        let dir = temp_dir("warc");
+        let warc_path = dir.join("a.warc");
+        let cdx_path = dir.join("a.cdx");
+
+        let mut writer = WarcWriter::create(warc_path.to_str().unwrap(),cdx_path.to_str().unwrap()).unwrap();
+        writer.write_info("easy-rss-cli").unwrap();
+        writer.write_exchange(&exchange("https://example.com/b",200,&[("Content-Encoding","gzip"),("Content-Length","3")],"<p>b</p>")).unwrap();
+        writer.write_exchange(&exchange("https://example.com/a?x=1",302,&[("Location","/b c")],"")).unwrap();
+        writer.finish().unwrap();
+
+        let warc = std::fs::read(&warc_path).unwrap();
+        let (head,block) = record(warc.as_slice());
+        assert!(head.starts_with("WARC/1.0\r\nWARC-Type: warcinfo\r\n"));
+        assert!(head.contains("WARC-Filename: a.warc"));
+        assert!(String::from_utf8(block).unwrap().starts_with("software: easy-rss-cli\r\n"));
+
+        let cdx = std::fs::read_to_string(&cdx_path).unwrap();
+        let lines:Vec<&str> = cdx.lines().collect();
+        assert_eq!(lines[0]," CDX N b a m s k r M S V g");
+        assert_eq!(lines.len(),3);
+        assert!(lines[1].starts_with("com,example)/a?x=1 20200102030405 https://example.com/a?x=1 text/html 302 "));
+        assert!(lines[1].contains(" /b%20c - "));
+
+        let mut ends = Vec::new();
+        for line in lines[1..].iter() {
+            let fields:Vec<&str> = line.split(' ').collect();
+            assert_eq!(fields.len(),11);
+            let (length,offset):(usize,usize) = (fields[8].parse().unwrap(),fields[9].parse().unwrap());
+            assert_eq!(fields[10],"a.warc");
+
+            let (head,block) = record(&warc[offset..offset+length]);
+            assert!(head.contains("WARC-Type: response"));
+            assert!(head.contains(format!("WARC-Target-URI: {}",fields[2]).as_str()));
+            assert!(head.contains(format!("WARC-Payload-Digest: sha1:{}",fields[5]).as_str()));
+            let block = String::from_utf8(block).unwrap();
+            assert!(block.starts_with(format!("HTTP/1.1 {} OK\r\n",fields[4]).as_str()));
+            assert!(!block.contains("gzip"));
+            ends.push(offset + length);
+
+            // The request record follows its response.
+            let (request,block) = record(&warc[offset+length..]);
+            assert!(request.contains("WARC-Type: request"));
+            assert!(String::from_utf8(block).unwrap().starts_with("GET /"));
+        }
+        assert!(ends.iter().all(|e| *e < warc.len()));
+        assert!(String::from_utf8_lossy(warc.as_slice()).contains("Content-Length: 8\r\n\r\n<p>b</p>"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}